@@ -1,14 +1,52 @@
-use std::{error::Error, fmt::Display, ops::{Deref, DerefMut, Not}, path::PathBuf, str::FromStr};
+use std::{error::Error, fmt::Display, io::Write, ops::{Deref, DerefMut, Not}, path::{Path, PathBuf}, str::FromStr, time::Duration};
 
+use chrono::Local;
 use eframe::egui::{self, Sense, WidgetText, text_edit::{CCursorRange, TextEditOutput, TextEditState}};
 
-use crate::{autocomplete_popup::{AutocompleteOutput, AutocompletePopup}, custom_window, snote::{self, snote_widget}};
+use crate::{autocomplete_popup::{AutocompleteOutput, AutocompletePopup, Ranked, rank}, custom_window, export, folders::{self, Folder}, index::NoteIndex, quick_snote::note_file_name, search::{self, Query, SearchHit, has_note_name}, saving::{SaveState, Saved}, settings::DEFAULT_TIMESTAMP_FORMAT, snapshots::{self, HistoryView, SNAPSHOT_INTERVAL}, snote::{self, History, LinkTarget, TIME_FORMAT, snote_widget}, tabs::{Tab, Tabs}, watcher::NoteWatcher};
 
 #[derive(Debug, Default)]
 pub struct Snotter {
     snots_dir: PathBuf,
     search_query: String,
-    note: (Option<PathBuf>, Option<snote::SNote>),
+    /// opened for `snots_dir` on the first search
+    index: Option<NoteIndex>,
+    /// started for `snots_dir` on the first frame
+    watcher: Option<NoteWatcher>,
+    /// the query the results were found for and its hits ranked by how well
+    /// they match, searching reads the matching notes so it only happens
+    /// when the query changes
+    search_results: Option<(String, Vec<Ranked<WidgetTextWrap<SearchHit>>>)>,
+    tabs: Tabs,
+    /// where the editor's cursor should move to on the next frame
+    jump_to: Option<usize>,
+    /// read from `snots_dir` when the sidebar is first shown
+    folders: Option<Folder>,
+    new_folder_name: String,
+    /// the last file operation that failed
+    error: Option<String>,
+    /// how new notes are named, like the ones `QuickSnote` makes
+    timestamp_format: String,
+    /// whether the undo history of notes is kept in `.snott/undo`
+    persist_undo: bool,
+    /// the snapshots of an open note, while they're shown
+    history_view: Option<HistoryView>,
+}
+
+/// What was done in the sidebar's folder tree
+enum FolderAction {
+    Open(PathBuf),
+    Move{note: PathBuf, to: PathBuf},
+    NewNote{folder: PathBuf},
+    CreateFolder{parent: PathBuf},
+}
+
+/// The state the sidebar's folder tree is drawn with
+struct FolderTree<'f>{
+    root: &'f Folder,
+    open: Option<&'f Path>,
+    new_folder_name: &'f mut String,
+    action: Option<FolderAction>,
 }
 
 struct ValueButton<T>{
@@ -63,38 +101,129 @@ impl From<WidgetTextWrap<PathBuf>> for WidgetText {
     }
 }
 
+impl std::fmt::Display for WidgetTextWrap<SearchHit> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+	let name = WidgetTextWrap(self.path().to_path_buf());
+	match &self.0 {
+	    SearchHit::Name{title: Some(title), ..} => write!(f, "{}  {}", name, title),
+	    SearchHit::Name{title: None, ..} => name.fmt(f),
+	    SearchHit::Line{line, context, ..} => write!(f, "{}:{}  {}", name, line + 1, context),
+	}
+    }
+}
+
+impl From<WidgetTextWrap<SearchHit>> for WidgetText {
+    fn from(other: WidgetTextWrap<SearchHit>) -> Self {
+        other.to_string().into()
+    }
+}
+
 impl eframe::App for Snotter {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         custom_window::custom_window_frame(ctx, frame, "snott", |ui| {
+	    egui::SidePanel::left("folders")
+		.resizable(true)
+		.show_inside(ui, |ui| self.folder_tree(ui));
+	    if self.tabs.active().is_some() {
+		egui::SidePanel::right("backlinks")
+		    .resizable(true)
+		    .show_inside(ui, |ui| self.backlinks(ui));
+	    }
+	    self.apply_disk_changes(ctx);
             ui.vertical_centered_justified(|ui| {
                 self.top_bar(ui);
+		if ui.input_mut().consume_key(egui::Modifiers::CTRL, egui::Key::N) {
+		    self.new_note_in(self.snots_dir.clone(), "");
+		}
+		let closed = self.tabs.handle_shortcuts(ui);
+		self.flush_closed(closed);
 
 		ui.add(self.search_bar(ctx));
+		let closed = self.tabs.tab_bar(ui);
+		self.flush_closed(closed);
 
+		self.conflict_bar(ui);
 		if self.snote_editor(ui).changed(){
-		    self.save_note().unwrap_or(())
+		    if let Some(tab) = self.tabs.active_mut() {
+			tab.saved.edited();
+		    }
 		};
 	    });
-	})
+	});
+	self.history_window(ctx);
+	self.autosave(ctx);
+    }
+
+    fn on_exit(&mut self, _gl: &eframe::glow::Context) {
+	for tab in self.tabs.iter_mut().filter(|tab| tab.saved.is_dirty()) {
+	    match save_note(&self.snots_dir, tab) {
+		Ok(Saved::Written) => {
+		    if let Err(e) = take_snapshot(&self.snots_dir, tab) {
+			eprintln!("failed to take a snapshot of {}: {}", tab.path.display(), e);
+		    }
+		    if let Some(index) = self.index.as_mut() {
+			index.update(&tab.path);
+		    }
+		},
+		Ok(Saved::Conflict) => (),
+		Err(e) => eprintln!("failed to save {}: {}", tab.path.display(), e),
+	    }
+	}
+	for tab in self.tabs.iter() {
+	    if let Err(e) = save_history(&self.snots_dir, tab, self.persist_undo) {
+		eprintln!("failed to save the history of {}: {}", tab.path.display(), e);
+	    }
+	}
+	if let Some(Err(e)) = self.index.as_mut().map(NoteIndex::save_changes) {
+	    eprintln!("failed to save the index of {}: {}", self.snots_dir.display(), e);
+	}
     }
 }
 
 
-type ACItem = AutocompleteOutput<WidgetTextWrap<PathBuf>>;
+type ACItem = AutocompleteOutput<WidgetTextWrap<SearchHit>>;
 impl Snotter {
+    pub fn with_time_format(self, time_format: impl AsRef<str>) -> Self{
+	Self{
+	    timestamp_format: time_format.as_ref().to_string(),
+	    ..self
+	}
+    }
+    pub fn with_persisted_undo(self, persist_undo: bool) -> Self{
+	Self{persist_undo, ..self}
+    }
     fn top_bar(&mut self, ui: &mut egui::Ui) {
         ui.horizontal_top(|ui| {
             egui::widgets::global_dark_light_mode_switch(ui);
-	    ui.add(self.snot_dir_button())
+	    ui.add(self.snot_dir_button());
+	    if ui.button("New note").clicked() {
+		self.new_note_in(self.snots_dir.clone(), "");
+	    }
+	    let history = ui.add_enabled(self.tabs.active().is_some(), egui::Button::new("History"));
+	    if history.on_hover_text("earlier versions of the note").clicked() {
+		self.open_history();
+	    }
+	    let export = ui.add_enabled(self.tabs.active().is_some(), egui::Button::new("Export HTML"));
+	    if export.on_hover_text("save the note as a page to share").clicked() {
+		self.export_html(ui.ctx());
+	    }
+	    if let Some(error) = &self.error {
+		ui.colored_label(egui::Color32::RED, error);
+	    }
         });
     }
     fn snot_dir_button(&mut self) -> impl egui::Widget + '_{
 	|ui: &mut egui::Ui|{
 	    let button = ui.button(self.snots_dir.display().to_string());
 	    if button.clicked() {
-		self.snots_dir = rfd::FileDialog::new()
-		    .pick_folder()
-		    .unwrap_or_else(|| self.snots_dir.clone());
+		let picked = rfd::FileDialog::new().pick_folder();
+		if let Some(Err(e)) = self.index.take().as_mut().map(NoteIndex::save_changes) {
+		    self.error = Some(format!("failed to save the index of {}: {}", self.snots_dir.display(), e));
+		}
+		self.snots_dir = picked.unwrap_or_else(|| self.snots_dir.clone());
+		self.watcher = None;
+		self.folders = None;
+		self.search_results = None;
 	    }
 	    button
 	}
@@ -104,16 +233,20 @@ impl Snotter {
 	    let TextEditOutput { response, state, .. } =
 		egui::TextEdit::singleline(&mut self.search_query)
 		.show(ui);
-	    let notes: Vec<_> = self
-		.get_matching_notes()
-		.iter()
-		.map(|f| WidgetTextWrap(f.to_path_buf()))
-		.collect();
-	    if response.gained_focus() || response.changed() {
-		if notes.is_empty().not() {
-		    ui.memory().open_popup(response.id.with("::ac"));
-		}
+	    if response.gained_focus() {
+		self.refresh_index();
+	    }
+	    let notes = self.get_matching_notes().to_vec();
+	    let query = Query::parse(&self.search_query);
+	    let popup = AutocompletePopup::ranked(notes, response.clone());
+	    if (response.gained_focus() || response.changed()) && popup.is_empty().not() {
+		ui.memory().open_popup(response.id.with("::ac"));
             }
+	    // nothing to pick, so enter makes a note titled by the query
+	    let submitted = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+	    if submitted && popup.is_empty() && query.text.is_empty().not() {
+		self.new_note_in(self.snots_dir.clone(), &query.text);
+	    }
 	    let ac_output = {
 		// let popup_response =
 		//     egui::popup_below_widget(ui, self.id, response, self.make_completion_widget());
@@ -132,8 +265,7 @@ impl Snotter {
 		// }
 		// ui.push_id("::ac", |ui|{
 		egui::popup_below_widget(ui, response.id.with("::ac"), &response,
-					 AutocompletePopup::new(notes, response.clone())
-					 .make_completion_widget())
+					 popup.make_completion_widget())
 		// }
 	    };
 	    self.update_from_autocomplete(ac_output.flatten(), ctx, state, response.id);
@@ -141,27 +273,248 @@ impl Snotter {
 	}
     }
 
-    fn save_note(&self) -> Result<(), Box<dyn Error>> {
-	if let (Some(file_path), Some(note)) = &self.note{
-	    std::fs::write(file_path, &note.raw_content)?;
+    /// Brings the note list and the open notes up to date with what other
+    /// programs (e.g. the sync tool) changed in `snots_dir`
+    fn apply_disk_changes(&mut self, ctx: &egui::Context) {
+	if self.watcher.is_none() && self.snots_dir.is_dir() {
+	    match NoteWatcher::watch(&self.snots_dir, ctx.clone()) {
+		Ok(watcher) => self.watcher = Some(watcher),
+		Err(e) => self.error = Some(format!("can't watch {}: {}", self.snots_dir.display(), e)),
+	    }
 	}
-	Ok(())
+	let changed = match self.watcher.as_ref().map(NoteWatcher::changed_paths) {
+	    Some(Ok(changed)) if !changed.is_empty() => changed,
+	    Some(Err(e)) => {
+		self.error = Some(format!("can't watch {}: {}", self.snots_dir.display(), e));
+		return;
+	    },
+	    _ => return,
+	};
+	self.folders = None;
+	self.search_results = None;
+	if let Some(index) = self.index.as_mut() {
+	    // a renamed folder only shows up as the folder's path
+	    match changed.iter().all(|path| has_note_name(path)) {
+		true => changed.iter().for_each(|path| { index.update(path); }),
+		false => if let Err(e) = index.refresh() {
+		    self.error = Some(format!("failed to save the index of {}: {}", self.snots_dir.display(), e));
+		},
+	    }
+	}
+	for tab in self.tabs.iter_mut().filter(|tab| changed.contains(&tab.path)) {
+	    match tab.saved.disk_changed(&tab.path) {
+		Ok(Some(content)) => tab.note.replace_all(&content),
+		Ok(None) => (),
+		Err(e) => self.error = Some(format!("failed to read {}: {}", tab.path.display(), e)),
+	    }
+	}
+    }
+
+    /// Saves the notes that weren't edited for a while
+    fn autosave(&mut self, ctx: &egui::Context) {
+	let due: Vec<_> = self.tabs.iter()
+	    .filter(|tab| tab.saved.is_due())
+	    .map(|tab| tab.path.clone())
+	    .collect();
+	for path in due {
+	    self.save_tab(&path);
+	}
+	// nothing else would wake the app up once the typing stops
+	if self.tabs.iter().any(|tab| tab.saved.is_dirty() && tab.saved.conflict.is_none()) {
+	    ctx.request_repaint();
+	}
+    }
+
+    fn save_tab(&mut self, path: &Path) {
+	let tab = match self.tabs.find_mut(path) {
+	    Some(tab) => tab,
+	    None => return,
+	};
+	match save_note(&self.snots_dir, tab) {
+	    Ok(Saved::Written) => {
+		let saved = take_snapshot(&self.snots_dir, tab)
+		    .and_then(|_| save_history(&self.snots_dir, tab, self.persist_undo));
+		match saved {
+		    Ok(()) => self.clear_save_error(path),
+		    Err(e) => self.error = Some(format!("failed to save the history of {}: {}", path.display(), e)),
+		}
+		if let Some(index) = self.index.as_mut() {
+		    index.update(path);
+		    self.search_results = None;
+		}
+	    },
+	    Ok(Saved::Conflict) => (),
+	    Err(e) => {
+		self.error = Some(format!("failed to save {}: {}", path.display(), e));
+		// try again after a while instead of on every frame
+		tab.saved.edited();
+	    },
+	}
+    }
+
+    /// Clears the error shown for an earlier failed save of `path` once it
+    /// was saved, errors about anything else are kept
+    fn clear_save_error(&mut self, path: &Path) {
+	let failed = [
+	    format!("failed to save {}: ", path.display()),
+	    format!("failed to save the history of {}: ", path.display()),
+	];
+	if self.error.as_ref().is_some_and(|error| failed.iter().any(|failed| error.starts_with(failed))) {
+	    self.error = None;
+	}
+    }
+
+    /// Saves the edits of a tab that was just closed, it's opened again
+    /// when they couldn't be saved
+    fn flush_closed(&mut self, closed: Option<Tab>) {
+	let mut tab = match closed {
+	    Some(tab) if tab.saved.is_dirty() => tab,
+	    _ => return,
+	};
+	match save_note(&self.snots_dir, &mut tab) {
+	    Ok(Saved::Written) => {
+		let saved = take_snapshot(&self.snots_dir, &tab)
+		    .and_then(|_| save_history(&self.snots_dir, &tab, self.persist_undo));
+		if let Err(e) = saved {
+		    self.error = Some(format!("failed to save the history of {}: {}", tab.path.display(), e));
+		}
+		if let Some(index) = self.index.as_mut() {
+		    index.update(&tab.path);
+		}
+	    },
+	    Ok(Saved::Conflict) => self.tabs.reopen(tab),
+	    Err(e) => {
+		self.error = Some(format!("failed to save {}: {}", tab.path.display(), e));
+		self.tabs.reopen(tab);
+	    },
+	}
+    }
+
+    /// Lets the user choose what to do with an open note that was changed
+    /// on disk while it was being edited
+    fn conflict_bar(&mut self, ui: &mut egui::Ui) {
+	let tab = match self.tabs.active_mut() {
+	    Some(tab) if tab.saved.conflict.is_some() => tab,
+	    _ => return,
+	};
+	ui.horizontal_wrapped(|ui| {
+	    ui.colored_label(
+		egui::Color32::RED,
+		format!("{} was changed on disk since it was opened", tab.name()),
+	    );
+	    if ui.button("Reload").on_hover_text("drop the edits made here").clicked() {
+		if let Some(theirs) = tab.saved.reload() {
+		    tab.note.replace_all(&theirs);
+		}
+	    }
+	    if ui.button("Merge").on_hover_text("combine both versions").clicked() {
+		if let Some(merged) = tab.saved.merge(&tab.note.raw_content) {
+		    tab.note.replace_all(&merged);
+		}
+	    }
+	    if ui.button("Keep mine").on_hover_text("overwrite the file on disk").clicked() {
+		if let Err(e) = tab.saved.overwrite(&tab.path, &tab.note.raw_content) {
+		    self.error = Some(format!("failed to save {}: {}", tab.path.display(), e));
+		}
+	    }
+	});
+    }
+
+    fn open_history(&mut self) {
+	let note = match self.tabs.active() {
+	    Some(tab) => tab.path.clone(),
+	    None => return,
+	};
+	match HistoryView::open(&self.snots_dir, &note) {
+	    Ok(view) => self.history_view = Some(view),
+	    Err(e) => self.error = Some(format!("failed to read the history of {}: {}", note.display(), e)),
+	}
+    }
+
+    /// Saves the active note as a page that looks like it does in the
+    /// editor, in the current theme
+    fn export_html(&mut self, ctx: &egui::Context) {
+	let tab = match self.tabs.active() {
+	    Some(tab) => tab,
+	    None => return,
+	};
+	let name = search::note_name(&tab.path);
+	let file = rfd::FileDialog::new()
+	    .set_directory(tab.path.parent().unwrap_or(&self.snots_dir))
+	    .set_file_name(&format!("{}.html", name))
+	    .add_filter("HTML", &["html"])
+	    .save_file();
+	if let Some(file) = file {
+	    let html = export::to_html(&tab.note, &name, &ctx.style());
+	    self.error = std::fs::write(&file, html).err()
+		.map(|e| format!("failed to export {}: {}", file.display(), e));
+	}
+    }
+
+    /// Shows the snapshots of a note while its tab is open, restoring one
+    /// is an edit that can be undone
+    fn history_window(&mut self, ctx: &egui::Context) {
+	let (view, tab) = match self.history_view.as_mut() {
+	    Some(view) => match self.tabs.find_mut(&view.note) {
+		Some(tab) => (view, tab),
+		None => {
+		    self.history_view = None;
+		    return;
+		},
+	    },
+	    None => return,
+	};
+	let mut open = true;
+	let restored = view.show(ctx, &tab.note.raw_content, &mut open);
+	if let Some(restored) = restored {
+	    // the content being replaced gets a snapshot too, so it can be
+	    // restored in turn
+	    match snapshots::take_snapshot(&self.snots_dir, &tab.path, &tab.note.raw_content, Duration::ZERO) {
+		Ok(_) => {
+		    tab.note.replace_all(&restored);
+		    tab.saved.edited();
+		    self.open_history();
+		},
+		Err(e) => self.error = Some(format!("failed to take a snapshot of {}: {}", tab.path.display(), e)),
+	    }
+	} else if !open {
+	    self.history_view = None;
+	}
+    }
+
+    /// Opens the index when search is first used, after that picks up notes
+    /// that changed outside of Snotter
+    fn refresh_index(&mut self) {
+	let refreshed = match self.index.as_mut() {
+	    Some(index) => index.refresh(),
+	    None => self.index.insert(NoteIndex::open(&self.snots_dir)).save_changes(),
+	};
+	if let Err(e) = refreshed {
+	    self.error = Some(format!("failed to save the index of {}: {}", self.snots_dir.display(), e));
+	}
+	self.search_results = None;
+	self.folders = None;
     }
-    fn get_matching_notes(&self) -> Vec<PathBuf> {
-        self.snots_dir
-            .read_dir()
-            .map(|d| {
-                d.filter_map(std::result::Result::ok)
-                    .filter(|f| f.path().extension() == Some("snot".as_ref()))
-                    .filter_map(|f| {
-                        f.file_type()
-                            .ok()
-                            .and_then(|f_t| f_t.is_file().then(|| f.path()))
-                    })
-                    .filter(|f| f.to_string_lossy().contains(self.search_query.as_str()))
-                    .collect()
-            })
-            .unwrap_or_default()
+    /// The index of `snots_dir`, opened when it's first needed
+    fn index(&mut self) -> &mut NoteIndex {
+	self.index.get_or_insert_with(|| NoteIndex::open(&self.snots_dir))
+    }
+    /// Nothing is found before the index was opened by using the search bar,
+    /// opening it reads every note
+    fn get_matching_notes(&mut self) -> &[Ranked<WidgetTextWrap<SearchHit>>] {
+	let index = match self.index.as_ref() {
+	    Some(index) => index,
+	    None => return &[],
+	};
+	let stale = self.search_results.as_ref()
+	    .is_none_or(|(query, _)| *query != self.search_query);
+	if stale {
+	    let query = Query::parse(&self.search_query);
+	    let hits = search::search_notes(index, &query);
+	    let ranked = rank(hits.into_iter().map(WidgetTextWrap), &query.text);
+	    self.search_results = Some((self.search_query.clone(), ranked));
+	}
+	self.search_results.as_ref().map_or(&[], |(_, hits)| hits)
     }
 
     fn update_from_autocomplete(
@@ -171,23 +524,18 @@ impl Snotter {
         search_bar: TextEditState,
 	id: egui::Id
     ) {
-        if let Some(AutocompleteOutput::Chosen(chosen)) = s {
-            self.select_file_from_autocomplete(chosen.0);
+        if let Some(AutocompleteOutput::Chosen(WidgetTextWrap(chosen))) = s {
+	    self.jump_to = Some(chosen.offset());
+            self.select_file_from_autocomplete(chosen.path().to_path_buf());
             self.update_cursor_from_autocomplete(ctx, search_bar, id);
         }
     }
 
     fn select_file_from_autocomplete(&mut self, chosen: PathBuf) {
-        let file_content = std::fs::read_to_string(&chosen)
-	    .ok()
-	    .or_else(||Some(format!("failed to read {}", chosen.display())));
-        let note = file_content
-	    .map(|s|
-		 snote::SNote::from_str(&s)
-		 .unwrap_or_else(|_|snote::SNote::new().set_raw(s))
-	    );
-        self.search_query = chosen.display().to_string();
-	self.note = (Some(chosen), note);
+        // the popup matches against the file name, not the whole path
+        self.search_query = WidgetTextWrap(chosen.clone()).to_string();
+	let persist_undo = self.persist_undo;
+	self.tabs.open(chosen, |path| load_note(&self.snots_dir, path, persist_undo));
     }
 
 
@@ -202,14 +550,253 @@ impl Snotter {
         }
     }
 
+    fn folder_tree(&mut self, ui: &mut egui::Ui) {
+	let root = self.folders
+	    .get_or_insert_with(|| Folder::read(&self.snots_dir))
+	    .clone();
+	let mut tree = FolderTree{
+	    root: &root,
+	    open: self.tabs.active().map(|tab| tab.path.as_path()),
+	    new_folder_name: &mut self.new_folder_name,
+	    action: None,
+	};
+	egui::ScrollArea::vertical().show(ui, |ui| tree.show(ui, &root));
+	if let Some(action) = tree.action {
+	    self.apply_folder_action(action);
+	}
+    }
+
+    fn apply_folder_action(&mut self, action: FolderAction) {
+	let done = match action {
+	    FolderAction::Open(note) => {
+		self.select_file_from_autocomplete(note);
+		return;
+	    },
+	    FolderAction::Move{note, to} => folders::move_note(&note, &to).and_then(|moved| {
+		if let Some(index) = self.index.as_mut() {
+		    index.update(&note);
+		    index.update(&moved);
+		}
+		if let Some(tab) = self.tabs.find_mut(&note) {
+		    tab.path = moved.clone();
+		}
+		snapshots::move_history(&self.snots_dir, &note, &moved).map_err(|e| {
+		    std::io::Error::new(e.kind(), format!("failed to move the history of {}: {}", note.display(), e))
+		})
+	    }),
+	    FolderAction::NewNote{folder} => {
+		self.new_note_in(folder, "");
+		return;
+	    },
+	    FolderAction::CreateFolder{parent} =>
+		folders::create_folder(&parent, &self.new_folder_name)
+		.map(|_| self.new_folder_name.clear()),
+	};
+	self.error = done.err().map(|e| e.to_string());
+	self.folders = None;
+	self.search_results = None;
+    }
+
+    /// Creates a note in `folder`, named the way `QuickSnote` names them,
+    /// and opens it. Failures are shown in the top bar.
+    fn new_note_in(&mut self, folder: PathBuf, title: &str) {
+	let created = self.create_note(&folder, title);
+	self.error = created.err().map(|e| e.to_string());
+    }
+
+    fn create_note(&mut self, folder: &Path, title: &str) -> Result<(), Box<dyn Error>> {
+	let format = match self.timestamp_format.is_empty() {
+	    true => DEFAULT_TIMESTAMP_FORMAT,
+	    false => &self.timestamp_format,
+	};
+	let now = Local::now();
+	let path = folder.join(note_file_name(&now, format));
+	// a headline so the parser has something to recognize from the start
+	let headline = format!("* {}", title);
+	let content = format!("{}\n:PROPERTIES:\n:created: {}\n:END:\n", headline, now.format(TIME_FORMAT));
+	std::fs::OpenOptions::new()
+	    .write(true)
+	    .create_new(true)
+	    .open(&path)?
+	    .write_all(content.as_bytes())?;
+	if let Some(index) = self.index.as_mut() {
+	    index.update(&path);
+	}
+	self.folders = None;
+	self.search_results = None;
+	self.jump_to = Some(headline.len());
+	self.select_file_from_autocomplete(path);
+	Ok(())
+    }
+
     fn snote_editor(&'_ mut self, ui: &mut egui::Ui) -> egui::Response{
-	self.note.1.as_mut().map(|note|{
-	    ui.add(snote_widget(&mut note.raw_content))
-	}).unwrap_or_else(||empty_widget(ui))
+	let jump_to = self.jump_to.take();
+	let mut opened_link = None;
+	let index = &self.index;
+	let link_targets = || index.as_ref().map(NoteIndex::link_targets).unwrap_or_default();
+	let response = self.tabs.active_mut().map(|tab|{
+	    let editor = snote_widget(&mut tab.note)
+		.jump_to(jump_to)
+		.opened_link(&mut opened_link)
+		.link_targets(&link_targets);
+	    ui.push_id(tab.id, |ui| ui.add(editor)).inner
+	}).unwrap_or_else(||empty_widget(ui));
+	match opened_link {
+	    Some(LinkTarget::Note(name)) => self.follow_link(&name),
+	    Some(LinkTarget::Url(url)) => self.open_url(ui.ctx(), &url),
+	    None => (),
+	}
+	response
+    }
+
+    /// Opens `url` with the system's handler, `file:` paths can start with
+    /// `~` or be relative to the open note
+    fn open_url(&self, ctx: &egui::Context, url: &str) {
+	let url = match url.strip_prefix("file:") {
+	    Some(path) if !path.starts_with("//") => {
+		let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+		let dir = self.tabs.active()
+		    .and_then(|tab| tab.path.parent())
+		    .unwrap_or(&self.snots_dir);
+		format!("file://{}", dir.join(path).display())
+	    },
+	    _ => url.to_string(),
+	};
+	ctx.output().open_url(url);
+    }
+
+    /// Opens the note a `[[name]]` link points to, a note titled `name` is
+    /// created when there's none yet
+    fn follow_link(&mut self, name: &str) {
+	match self.index().resolve(name).map(Path::to_path_buf) {
+	    Some(note) => self.select_file_from_autocomplete(note),
+	    None => self.new_note_in(self.snots_dir.clone(), name),
+	}
+    }
+
+    /// The notes linking to the open one, clicking one opens it
+    fn backlinks(&mut self, ui: &mut egui::Ui) {
+	let open = match self.tabs.active() {
+	    Some(tab) => tab.path.clone(),
+	    None => return,
+	};
+	ui.strong("Linked from");
+	let linking: Vec<_> = self.index()
+	    .backlinks(&open)
+	    .into_iter()
+	    .map(Path::to_path_buf)
+	    .collect();
+	if linking.is_empty() {
+	    ui.label(egui::RichText::new("no notes link here").weak());
+	}
+	let mut opened = None;
+	egui::ScrollArea::vertical().show(ui, |ui| {
+	    for note in linking {
+		let name = WidgetTextWrap(note.clone()).to_string();
+		if ui.link(name).on_hover_text(note.display().to_string()).clicked() {
+		    opened = Some(note);
+		}
+	    }
+	});
+	if let Some(note) = opened {
+	    self.select_file_from_autocomplete(note);
+	}
+    }
+}
+
+
+
+impl<'f> FolderTree<'f> {
+    fn show(&mut self, ui: &mut egui::Ui, folder: &'f Folder) {
+	let header = egui::CollapsingHeader::new(folder.name())
+	    .id_source(&folder.path)
+	    .default_open(folder.path == self.root.path)
+	    .show(ui, |ui| {
+		folder.folders.iter().for_each(|sub| self.show(ui, sub));
+		folder.notes.iter().for_each(|note| self.note(ui, folder, note));
+	    });
+	header.header_response.context_menu(|ui| {
+	    if ui.button("New note").clicked() {
+		self.action = Some(FolderAction::NewNote{folder: folder.path.clone()});
+		ui.close_menu();
+	    }
+	    ui.separator();
+	    ui.text_edit_singleline(self.new_folder_name);
+	    if ui.button("New folder").clicked() {
+		self.action = Some(FolderAction::CreateFolder{parent: folder.path.clone()});
+		ui.close_menu();
+	    }
+	});
+    }
+
+    fn note(&mut self, ui: &mut egui::Ui, folder: &Folder, note: &Path) {
+	let name = WidgetTextWrap(note.to_path_buf()).to_string();
+	let label = ui.selectable_label(self.open == Some(note), name);
+	if label.clicked() {
+	    self.action = Some(FolderAction::Open(note.to_path_buf()));
+	}
+	label.context_menu(|ui| {
+	    ui.menu_button("Move to", |ui| {
+		let targets = self.root.all_folders().into_iter().filter(|f| f.path != folder.path);
+		for target in targets {
+		    let name = target.path.strip_prefix(&self.root.path)
+			.ok()
+			.filter(|relative| !relative.as_os_str().is_empty())
+			.map_or_else(|| self.root.name(), |relative| relative.display().to_string());
+		    if ui.button(name).clicked() {
+			self.action = Some(FolderAction::Move{
+			    note: note.to_path_buf(),
+			    to: target.path.clone(),
+			});
+			ui.close_menu();
+		    }
+		}
+	    });
+	});
+    }
+}
+
+fn load_note(snots_dir: &Path, path: &Path, persist_undo: bool) -> (snote::SNote, SaveState) {
+    let (file_content, saved) = match SaveState::load(path) {
+	Ok(loaded) => loaded,
+	Err(e) => {
+	    let error = format!("failed to read {}: {}", path.display(), e);
+	    return (snote::SNote::new().set_raw(error), SaveState::default());
+	},
+    };
+    let mut note = snote::SNote::from_str(&file_content)
+	.unwrap_or_else(|_|snote::SNote::new().set_raw(&file_content));
+    if persist_undo {
+	note.history = History::load(&snapshots::undo_file(snots_dir, path), &file_content);
+    }
+    (note, saved)
+}
+
+/// Writes the edits of a note, what its file had when it was opened gets a
+/// snapshot before it's first overwritten
+fn save_note(snots_dir: &Path, tab: &mut Tab) -> std::io::Result<Saved> {
+    if !tab.original_kept {
+	snapshots::snapshot_original(snots_dir, &tab.path, tab.saved.base())?;
+	tab.original_kept = true;
     }
+    tab.saved.save(&tab.path, &tab.note.raw_content)
 }
 
+/// Takes a snapshot of a note that was just saved, unless one was taken
+/// recently
+fn take_snapshot(snots_dir: &Path, tab: &Tab) -> Result<(), Box<dyn Error>> {
+    snapshots::take_snapshot(snots_dir, &tab.path, &tab.note.raw_content, SNAPSHOT_INTERVAL)?;
+    Ok(())
+}
 
+/// Keeps the history of a saved note in `snots_dir`, so it can still be
+/// undone after the note is opened again
+fn save_history(snots_dir: &Path, tab: &Tab, persist_undo: bool) -> Result<(), Box<dyn Error>> {
+    match persist_undo && !tab.saved.is_dirty() {
+	true => tab.note.history.save(&snapshots::undo_file(snots_dir, &tab.path), &tab.note.raw_content),
+	false => Ok(()),
+    }
+}
 
 fn empty_widget(ui: &mut egui::Ui) -> egui::Response{
     ui.allocate_response(
@@ -1,6 +1,12 @@
-use std::fmt::Debug;
+use std::cmp::Reverse;
+use std::fmt::{Debug, Display};
 
-use eframe::{egui::{self, WidgetText}, emath::NumExt};
+use eframe::{egui::{self, TextFormat, WidgetText}, emath::NumExt};
+
+use crate::fuzzy::fuzzy_match;
+
+/// How wide a popup shown with [`AutocompletePopup::show_at`] is
+const POPUP_WIDTH: f32 = 300.0;
 
 #[derive(Debug)]
 pub(crate) struct AutocompletePopup<C>
@@ -8,8 +14,20 @@ where
     C: Clone + Into<WidgetText>,
 {
     items: Vec<C>,
+    /// the label and matched char indices of every item, once ranked
+    highlights: Vec<Option<(String, Vec<usize>)>>,
     id: egui::Id,
-    response: egui::Response,
+    /// the widget the popup completes, if it's shown below one
+    response: Option<egui::Response>,
+}
+
+/// An item that fuzzy matched a query, with its label and the char indices
+/// of the label that matched
+#[derive(Debug, Clone)]
+pub(crate) struct Ranked<C> {
+    pub item: C,
+    label: String,
+    positions: Vec<usize>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -34,13 +52,61 @@ where
     C: Clone + Into<WidgetText> + std::fmt::Debug,
 {
     pub fn new(items: impl IntoIterator<Item = C>, parent: egui::Response) -> Self {
+        let items: Vec<_> = items.into_iter().collect();
         Self {
-            items: items.into_iter().collect(),
+            highlights: vec![None; items.len()],
+            items,
             id: parent.id.with("::ac"),
-	    response: parent
+	    response: Some(parent)
         }
     }
 
+    /// A popup of items ranked with [`rank`], with the matched chars of their
+    /// labels emphasized
+    pub fn ranked(ranked: impl IntoIterator<Item = Ranked<C>>, parent: egui::Response) -> Self {
+	Self::from_ranked(ranked, parent.id.with("::ac"), Some(parent))
+    }
+
+    /// Like [`Self::ranked`] for a popup that isn't shown below a widget but
+    /// with [`Self::show_at`], `id` is the popup's id in `egui::Memory`
+    pub fn ranked_with_id(ranked: impl IntoIterator<Item = Ranked<C>>, id: egui::Id) -> Self {
+	Self::from_ranked(ranked, id, None)
+    }
+
+    fn from_ranked(ranked: impl IntoIterator<Item = Ranked<C>>, id: egui::Id, response: Option<egui::Response>) -> Self {
+	let (highlights, items) = ranked.into_iter()
+	    .map(|ranked| (Some((ranked.label, ranked.positions)), ranked.item))
+	    .unzip();
+	Self{items, highlights, id, response}
+    }
+
+    /// Shows the popup at `pos` while it's the open popup, like
+    /// `egui::popup_below_widget` does below a widget
+    pub fn show_at(&self, ui: &egui::Ui, pos: egui::Pos2) -> Option<AutocompleteOutput<C>> {
+	if !ui.memory().is_popup_open(self.id) {
+	    return None;
+	}
+	let output = egui::Area::new(self.id)
+	    .order(egui::Order::Foreground)
+	    .fixed_pos(pos)
+	    .show(ui.ctx(), |ui| {
+		egui::Frame::popup(ui.style()).show(ui, |ui| {
+		    ui.set_width(POPUP_WIDTH);
+		    let layout = egui::Layout::top_down_justified(egui::Align::LEFT);
+		    ui.with_layout(layout, self.make_completion_widget()).inner
+		}).inner
+	    })
+	    .inner;
+	if ui.input().key_pressed(egui::Key::Escape) {
+	    ui.memory().close_popup();
+	}
+	output
+    }
+
+    pub fn is_empty(&self) -> bool {
+	self.items.is_empty()
+    }
+
     fn update_selection_by_keyboard(selection: Selection, ui: &mut egui::Ui) -> Selection {
         use egui::{Key, Modifiers};
         if ui.input_mut().consume_key(Modifiers::NONE, Key::ArrowUp) {
@@ -123,9 +189,52 @@ where
 
     fn draw_label(&self, ui: &mut egui::Ui, selected_num: usize, row_num: usize) -> egui::Response {
         let is_marked = selected_num == row_num;
-        let item = self.items[row_num].clone();
-        ui.selectable_label(is_marked, item)
+        match &self.highlights[row_num] {
+	    Some((label, positions)) => {
+		let label = highlighted_label(ui, label, positions);
+		ui.selectable_label(is_marked, label)
+	    },
+	    None => ui.selectable_label(is_marked, self.items[row_num].clone()),
+	}
+    }
+}
+
+/// The items that fuzzy match `query`, best matches first. Equally good
+/// matches keep their order, so items should be passed most relevant (e.g.
+/// most recent) first.
+pub(crate) fn rank<C: Display>(items: impl IntoIterator<Item = C>, query: &str) -> Vec<Ranked<C>> {
+    let mut ranked: Vec<_> = items.into_iter()
+	.filter_map(|item| {
+	    let label = item.to_string();
+	    fuzzy_match(query, &label).map(|found| (found.score, Ranked{item, label, positions: found.positions}))
+	})
+	.collect();
+    ranked.sort_by_key(|(score, _)| Reverse(*score));
+    ranked.into_iter().map(|(_, ranked)| ranked).collect()
+}
+
+/// `label` with the chars at the `matched` char indices emphasized
+fn highlighted_label(ui: &egui::Ui, label: &str, matched: &[usize]) -> egui::text::LayoutJob {
+    let visuals = ui.visuals();
+    let plain = TextFormat{
+	font_id: egui::TextStyle::Button.resolve(ui.style()),
+	color: visuals.text_color(),
+	..Default::default()
+    };
+    let emphasized = TextFormat{
+	color: visuals.strong_text_color(),
+	underline: egui::Stroke::new(1.0, visuals.strong_text_color()),
+	..plain.clone()
+    };
+    let mut job = egui::text::LayoutJob::default();
+    for (i, c) in label.chars().enumerate() {
+	let format = match matched.contains(&i) {
+	    true => emphasized.clone(),
+	    false => plain.clone(),
+	};
+	job.append(c.encode_utf8(&mut [0; 4]), 0.0, format);
     }
+    job
 }
 
 fn check_mouse_interactions((response, i): (egui::Response, usize)) -> Option<Selection> {
@@ -144,7 +253,8 @@ impl<C: Into<WidgetText> + Clone + Debug> egui::Widget for AutocompletePopup<C>{
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
         let w = self.make_completion_widget();
 	w(ui);
-	self.response
+	let id = self.id;
+	self.response.unwrap_or_else(|| ui.interact(ui.min_rect(), id, egui::Sense::hover()))
     }
 }
 impl AutocompleteOutput<usize> {
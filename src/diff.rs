@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+/// A line of a line by line diff between an old and a new text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DiffLine<'t> {
+    Same(&'t str),
+    Removed(&'t str),
+    Added(&'t str),
+}
+
+/// The lines of `old` and `new` matched up by their longest common subsequence
+pub(crate) fn diff_lines<'t>(old: &'t str, new: &'t str) -> Vec<DiffLine<'t>> {
+    let (old, new): (Vec<_>, Vec<_>) = (old.lines().collect(), new.lines().collect());
+    let mut diff = Vec::with_capacity(old.len().max(new.len()));
+    let (mut o, mut n) = (0, 0);
+    for (same_o, same_n) in common_lines(&old, &new) {
+	diff.extend(old[o..same_o].iter().map(|l| DiffLine::Removed(l)));
+	diff.extend(new[n..same_n].iter().map(|l| DiffLine::Added(l)));
+	diff.push(DiffLine::Same(old[same_o]));
+	(o, n) = (same_o + 1, same_n + 1);
+    }
+    diff.extend(old[o..].iter().map(|l| DiffLine::Removed(l)));
+    diff.extend(new[n..].iter().map(|l| DiffLine::Added(l)));
+    diff
+}
+
+/// The index pairs of the lines `a` and `b` have in common, in order
+fn common_lines(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    let mut common = vec![];
+    add_common_lines(a, b, (0, 0), &mut common);
+    common
+}
+
+/// Adds the lines `a` and `b` have in common, offset by where they start,
+/// to `common`. The common start and end are set aside before the rest
+/// is split where Myers' diff finds the middle of the shortest edit, so
+/// only memory in the order of the lines is needed.
+fn add_common_lines(a: &[&str], b: &[&str], (i, j): (usize, usize), common: &mut Vec<(usize, usize)>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    common.extend((0..prefix).map(|n| (i + n, j + n)));
+    let (a, b, i, j) = (&a[prefix..], &b[prefix..], i + prefix, j + prefix);
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+    if !a.is_empty() && !b.is_empty() {
+	if let Some((x, y)) = middle_of_edit(a, b) {
+	    add_common_lines(&a[..x], &b[..y], (i, j), common);
+	    add_common_lines(&a[x..], &b[y..], (i + x, j + y), common);
+	}
+    }
+    common.extend((0..suffix).map(|n| (i + a.len() + n, j + b.len() + n)));
+}
+
+/// Where the paths of the shortest edit from the start of `a` and `b` and
+/// from their ends meet, `None` when they have nothing in common
+fn middle_of_edit(a: &[&str], b: &[&str]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    // the furthest x reached on each diagonal k = x - y, from the start
+    // and from the end, -1 where none was yet
+    let mut forward = vec![-1; 2 * max_d as usize + 2];
+    let mut backward = forward.clone();
+    forward[offset as usize + 1] = 0;
+    backward[offset as usize + 1] = 0;
+    let delta = n - m;
+    let meet_forward = delta % 2 != 0;
+    // diagonals leaving the grid aren't followed any further
+    let (mut forward_start, mut forward_end, mut backward_start, mut backward_end) = (0, 0, 0, 0);
+    for d in 0..max_d {
+	for k in (-d + forward_start..=d - forward_end).step_by(2) {
+	    let at = (offset + k) as usize;
+	    let mut x = match k == -d || (k != d && forward[at - 1] < forward[at + 1]) {
+		true => forward[at + 1],
+		false => forward[at - 1] + 1,
+	    };
+	    let mut y = x - k;
+	    while x < n && y < m && a[x as usize] == b[y as usize] {
+		(x, y) = (x + 1, y + 1);
+	    }
+	    forward[at] = x;
+	    if x > n {
+		forward_end += 2;
+	    } else if y > m {
+		forward_start += 2;
+	    } else if meet_forward {
+		let other = offset + delta - k;
+		if (0..backward.len() as isize).contains(&other) && backward[other as usize] != -1
+		    && x >= n - backward[other as usize] {
+		    return Some((x as usize, y as usize));
+		}
+	    }
+	}
+	for k in (-d + backward_start..=d - backward_end).step_by(2) {
+	    let at = (offset + k) as usize;
+	    let mut x = match k == -d || (k != d && backward[at - 1] < backward[at + 1]) {
+		true => backward[at + 1],
+		false => backward[at - 1] + 1,
+	    };
+	    let mut y = x - k;
+	    while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+		(x, y) = (x + 1, y + 1);
+	    }
+	    backward[at] = x;
+	    if x > n {
+		backward_end += 2;
+	    } else if y > m {
+		backward_start += 2;
+	    } else if !meet_forward {
+		let other = offset + delta - k;
+		if (0..forward.len() as isize).contains(&other) && forward[other as usize] != -1 {
+		    let forward_x = forward[other as usize];
+		    if forward_x >= n - x {
+			return Some((forward_x as usize, (forward_x - (other - offset)) as usize));
+		    }
+		}
+	    }
+	}
+    }
+    None
+}
+
+/// Combines the changes `mine` and `theirs` made to `base`, where both
+/// changed the same lines differently both versions are kept between
+/// conflict markers
+pub(crate) fn merge3(base: &str, mine: &str, theirs: &str) -> String {
+    let trailing_newline = mine.ends_with('\n');
+    let lines = |text| str::lines(text).collect::<Vec<_>>();
+    let (base, mine, theirs) = (lines(base), lines(mine), lines(theirs));
+    let in_mine = common_lines(&base, &mine);
+    let in_theirs: HashMap<_, _> = common_lines(&base, &theirs).into_iter().collect();
+    // base lines kept by both sides split the texts into chunks that can
+    // be merged on their own
+    let mut stable = in_mine.iter()
+	.filter_map(|&(b, m)| in_theirs.get(&b).map(|&t| (b, m, t)))
+	.peekable();
+
+    let mut merged: Vec<&str> = Vec::new();
+    let (mut b, mut m, mut t) = (0, 0, 0);
+    loop {
+	let (next_b, next_m, next_t) = stable.peek().copied()
+	    .unwrap_or((base.len(), mine.len(), theirs.len()));
+	let (base_chunk, mine_chunk, theirs_chunk) =
+	    (&base[b..next_b], &mine[m..next_m], &theirs[t..next_t]);
+	if mine_chunk == base_chunk || mine_chunk == theirs_chunk {
+	    merged.extend(theirs_chunk);
+	} else if theirs_chunk == base_chunk {
+	    merged.extend(mine_chunk);
+	} else {
+	    merged.push("<<<<<<< this device");
+	    merged.extend(mine_chunk);
+	    merged.push("=======");
+	    merged.extend(theirs_chunk);
+	    merged.push(">>>>>>> on disk");
+	}
+	match stable.next() {
+	    Some((sb, sm, st)) => {
+		merged.push(base[sb]);
+		(b, m, t) = (sb + 1, sm + 1, st + 1);
+	    },
+	    None => break,
+	}
+    }
+    let mut merged = merged.join("\n");
+    if trailing_newline && !merged.is_empty() {
+	merged.push('\n');
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiffLine::*, common_lines, diff_lines, merge3};
+
+    /// The length of the longest common subsequence, the slow way
+    fn lcs_length(a: &[&str], b: &[&str]) -> usize {
+	let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+	for i in (0..a.len()).rev() {
+	    for j in (0..b.len()).rev() {
+		lengths[i][j] = match a[i] == b[j] {
+		    true => lengths[i + 1][j + 1] + 1,
+		    false => lengths[i + 1][j].max(lengths[i][j + 1]),
+		};
+	    }
+	}
+	lengths[0][0]
+    }
+
+    #[test]
+    fn common_lines_are_a_longest_common_subsequence() {
+	// texts of a few distinct lines, so there's a lot in common to find
+	let mut seed: u64 = 1;
+	let mut text = |len: u64| -> Vec<&str> {
+	    (0..len).map(|_| {
+		seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+		["a", "b", "c", "d"][(seed >> 33) as usize % 4]
+	    }).collect()
+	};
+	for len in 0..200 {
+	    let (a, b) = (text(len % 23), text(len % 17));
+	    let common = common_lines(&a, &b);
+	    assert!(common.iter().all(|&(i, j)| a[i] == b[j]), "{:?} {:?}", a, b);
+	    assert!(common.windows(2).all(|pair| pair[0].0 < pair[1].0 && pair[0].1 < pair[1].1));
+	    assert_eq!(lcs_length(&a, &b), common.len(), "{:?} {:?}", a, b);
+	}
+    }
+
+    #[test]
+    fn long_texts_with_few_changes() {
+	let old: Vec<String> = (0..20000).map(|n| n.to_string()).collect();
+	let mut new = old.clone();
+	new[10] = "changed".to_string();
+	new.insert(15000, "inserted".to_string());
+	let (old, new): (Vec<&str>, Vec<&str>) = (old.iter().map(String::as_str).collect(), new.iter().map(String::as_str).collect());
+	assert_eq!(19999, common_lines(&old, &new).len());
+    }
+
+    #[test]
+    fn diff_keeps_common_lines() {
+	assert_eq!(
+	    vec![Same("a"), Removed("b"), Added("B"), Same("c"), Added("d")],
+	    diff_lines("a\nb\nc", "a\nB\nc\nd")
+	);
+	assert_eq!(vec![Removed("a")], diff_lines("a", ""));
+    }
+
+    #[test]
+    fn merging_changes_to_different_lines() {
+	let base = "* title\none\ntwo\nthree";
+	let mine = "* title\nONE\ntwo\nthree";
+	let theirs = "* title\none\ntwo\nthree\nfour";
+	assert_eq!("* title\nONE\ntwo\nthree\nfour", merge3(base, mine, theirs));
+	assert_eq!(mine, merge3(base, mine, base));
+	assert_eq!(theirs, merge3(base, base, theirs));
+    }
+
+    #[test]
+    fn merging_the_same_change_twice() {
+	assert_eq!("a\nB", merge3("a\nb", "a\nB", "a\nB"));
+    }
+
+    #[test]
+    fn conflicting_changes_keep_both_versions() {
+	let merged = merge3("a\nb\nc", "a\nmine\nc", "a\ntheirs\nc");
+	assert_eq!(
+	    "a\n<<<<<<< this device\nmine\n=======\ntheirs\n>>>>>>> on disk\nc",
+	    merged
+	);
+    }
+}
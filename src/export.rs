@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use eframe::egui::{self, Color32, FontFamily, Stroke};
+
+use crate::search::note_name;
+use crate::snote::{ListKind, Markup, SNote, SNoteSection, TableRow, TaskKind, parse_inlines, snote_layout_job};
+
+/// What notes can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    /// in snotter's dark theme
+    Html,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+	match self {
+	    Format::Markdown => "md",
+	    Format::Html => "html",
+	}
+    }
+
+    pub fn export(&self, name: &str, note: &SNote) -> String {
+	match self {
+	    Format::Markdown => to_markdown(note),
+	    Format::Html => to_html(note, name, &egui::Style{
+		visuals: egui::Visuals::dark(),
+		..Default::default()
+	    }),
+	}
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+	match s.to_lowercase().as_str() {
+	    "md" | "markdown" => Ok(Format::Markdown),
+	    "html" | "htm" => Ok(Format::Html),
+	    _ => Err(format!("unknown format {}, expected md or html", s)),
+	}
+    }
+}
+
+/// `snotter export --format md|html [--output PATH] NOTE...`
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExportArgs {
+    pub format: Format,
+    /// a file for a single note, a directory for many, stdout when missing
+    pub output: Option<PathBuf>,
+    pub notes: Vec<PathBuf>,
+}
+
+impl ExportArgs {
+    pub const USAGE: &'static str = "usage: snotter export --format md|html [--output PATH] NOTE...";
+
+    /// Reads the arguments after `export`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+	let mut format = None;
+	let mut output = None;
+	let mut notes = vec![];
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+	    let (flag, inline_value) = match arg.split_once('=') {
+		Some((flag, value)) if flag.starts_with('-') => (flag, Some(value.to_string())),
+		_ => (arg.as_str(), None),
+	    };
+	    let mut value = || inline_value.clone().or_else(|| args.next().cloned())
+		.ok_or_else(|| format!("{} needs a value\n{}", flag, Self::USAGE));
+	    match flag {
+		"-f" | "--format" => format = Some(value()?.parse()?),
+		"-o" | "--output" => output = Some(PathBuf::from(value()?)),
+		_ if flag.starts_with('-') => return Err(format!("unknown option {}\n{}", flag, Self::USAGE)),
+		_ => notes.push(PathBuf::from(arg)),
+	    }
+	}
+	if notes.is_empty() {
+	    return Err(format!("no notes to export\n{}", Self::USAGE));
+	}
+	Ok(Self{format: format.unwrap_or(Format::Markdown), output, notes})
+    }
+}
+
+/// Runs `snotter export`, many notes are written to the output directory
+/// as `<note name>.<extension>`
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let ExportArgs{format, output, notes} = ExportArgs::parse(args)?;
+    let export = |path: &Path| -> Result<String, Box<dyn Error>> {
+	let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+	Ok(format.export(&note_name(path), &SNote::from_str(&content)?))
+    };
+    match (output, notes.as_slice()) {
+	(None, notes) => {
+	    for note in notes {
+		print!("{}", export(note)?);
+	    }
+	},
+	(Some(file), [note]) if !file.is_dir() => std::fs::write(file, export(note)?)?,
+	(Some(dir), notes) => {
+	    let names = export_names(notes)?;
+	    std::fs::create_dir_all(&dir)?;
+	    for (note, name) in notes.iter().zip(names) {
+		let file = dir.join(format!("{}.{}", name, format.extension()));
+		std::fs::write(file, export(note)?)?;
+	    }
+	},
+    }
+    Ok(())
+}
+
+/// The names `notes` are exported to a directory under, notes in different
+/// folders can have the same name and one would overwrite the other
+fn export_names(notes: &[PathBuf]) -> Result<Vec<String>, String> {
+    let mut exported: HashMap<String, &PathBuf> = HashMap::new();
+    let mut names = vec![];
+    for note in notes {
+	let name = note_name(note);
+	if let Some(other) = exported.insert(name.clone(), note) {
+	    return Err(format!("{} and {} would both be exported as {}", other.display(), note.display(), name));
+	}
+	names.push(name);
+    }
+    Ok(names)
+}
+
+/// The note as a page that needs nothing else to be shown, laid out the
+/// way the editor shows it with `style`
+pub fn to_html(note: &SNote, title: &str, style: &egui::Style) -> String {
+    // the layout needs a frame's fonts, so it's made in a frame of its own
+    let ctx = egui::Context::default();
+    ctx.set_style(style.clone());
+    let mut job = None;
+    let _ = ctx.run(Default::default(), |ctx| {
+	egui::CentralPanel::default().show(ctx, |ui| {
+	    job = Some(snote_layout_job(ui, &note.raw_content, &note.sections()));
+	});
+    });
+    let mut body = String::new();
+    for section in job.map(|job| job.sections).unwrap_or_default() {
+	if section.leading_space > 0.0 {
+	    body.push_str(&format!("<span style=\"display:inline-block;width:{}px\"></span>",
+				   section.leading_space));
+	}
+	let format = &section.format;
+	let mut css = vec![
+	    format!("font-size:{}px", format.font_id.size),
+	    format!("color:{}", css_color(format.color)),
+	];
+	if format.font_id.family == FontFamily::Monospace {
+	    css.push("font-family:monospace".to_string());
+	}
+	if format.background != Color32::TRANSPARENT {
+	    css.push(format!("background:{}", css_color(format.background)));
+	}
+	if format.italics {
+	    css.push("font-style:italic".to_string());
+	}
+	for (stroke, line) in [(format.underline, "underline"), (format.strikethrough, "line-through")] {
+	    if stroke != Stroke::none() {
+		css.push(format!("text-decoration:{} {}", line, css_color(stroke.color)));
+	    }
+	}
+	let text = escape_html(&note.raw_content[section.byte_range.clone()]);
+	body.push_str(&format!("<span style=\"{}\">{}</span>", css.join(";"), text));
+    }
+    format!(
+	"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+	 <body style=\"margin:0;background:{}\">\n\
+	 <pre style=\"margin:0;padding:1em;white-space:pre-wrap;font-family:sans-serif\">{}</pre>\n\
+	 </body>\n</html>\n",
+	escape_html(title), css_color(style.visuals.extreme_bg_color), body
+    )
+}
+
+fn css_color(color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    format!("rgba({},{},{},{:.3})", r, g, b, a as f32 / 255.0)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The note as CommonMark, with GitHub's tables and task lists. Properties
+/// become front matter and `[[links]]` link to `<name>.md`, the way
+/// exported notes are named.
+pub fn to_markdown(note: &SNote) -> String {
+    let text = note.raw_content.as_str();
+    let mut blocks = vec![];
+    let properties = note.properties().to_map();
+    if !properties.is_empty() {
+	let lines: Vec<_> = properties.iter()
+	    .map(|(key, value)| format!("{}: {}", key, serde_json::Value::from(value.as_str())))
+	    .collect();
+	blocks.push(format!("---\n{}\n---", lines.join("\n")));
+    }
+    markdown_blocks(text, &note.sections(), &mut blocks);
+    let mut markdown = blocks.join("\n\n");
+    markdown.push('\n');
+    markdown
+}
+
+fn markdown_blocks(text: &str, sections: &[SNoteSection], blocks: &mut Vec<String>) {
+    let mut sections = sections.iter().peekable();
+    while let Some(section) = sections.next() {
+	match section {
+	    SNoteSection::Headline{level, task, span, inlines, children} => {
+		let start = task.as_ref().map_or(span.start + level + 1, |task| task.span.start);
+		let (title, tags) = headline_tags(text, start..span.end, inlines);
+		let title = markdown_inlines(text, title, inlines);
+		// Markdown has no headings below level 6
+		let mut headline = format!("{} {}", "#".repeat((*level).min(6)), title.trim_end());
+		for tag in tags {
+		    headline.push_str(&format!(" #{}", &text[tag.content_span()]));
+		}
+		blocks.push(headline.trim_end().to_string());
+		markdown_blocks(text, children, blocks);
+	    },
+	    SNoteSection::Paragraph(span, inlines) => {
+		let paragraph = markdown_inlines(text, span.clone(), inlines);
+		let lines: Vec<_> = paragraph.lines().map(escape_block_start).collect();
+		blocks.push(lines.join("\n").trim_end().to_string());
+	    },
+	    SNoteSection::ListItem{..} => {
+		// the items right after it are part of the same list, a blank
+		// line starts another one
+		let mut lines = vec![];
+		let mut item = section;
+		markdown_list_item(text, item, "", &mut lines);
+		while let Some(next) = sections.next_if(|next| {
+		    matches!(next, SNoteSection::ListItem{..}) && !ends_with_blank_line(&text[item.span()])
+		}) {
+		    markdown_list_item(text, next, "", &mut lines);
+		    item = next;
+		}
+		blocks.push(lines.join("\n"));
+		if sections.peek().is_some_and(|next| matches!(next, SNoteSection::ListItem{..})) {
+		    // without something between them Markdown joins the lists
+		    blocks.push("<!-- -->".to_string());
+		}
+	    },
+	    SNoteSection::CodeBlock{language, code, ..} => {
+		let code = text[code.clone()].trim_end_matches(['\n', '\r']);
+		let mut fence = "```".to_string();
+		while code.contains(fence.as_str()) {
+		    fence.push('`');
+		}
+		let language = language.clone().map_or("", |language| &text[language]);
+		match code.is_empty() {
+		    true => blocks.push(format!("{}{}\n{}", fence, language, fence)),
+		    false => blocks.push(format!("{}{}\n{}\n{}", fence, language, code, fence)),
+		}
+	    },
+	    SNoteSection::Table{rows, ..} => blocks.push(markdown_table(text, rows)),
+	    // the note's properties are the front matter
+	    SNoteSection::Properties{..} => (),
+	    SNoteSection::Markup(..) |
+	    SNoteSection::Tag(_) |
+	    SNoteSection::Link(_) |
+	    SNoteSection::Url(_) => blocks.push(markdown_inlines(text, section.span(), std::slice::from_ref(section))),
+	}
+    }
+}
+
+/// A headline's title and the `:tags:` at its end, which Markdown has no
+/// place for other than as `#tags`
+fn headline_tags<'s>(text: &str, line: Range<usize>, inlines: &'s [SNoteSection])
+		     -> (Range<usize>, Vec<&'s SNoteSection>) {
+    let tags: Vec<_> = inlines.iter()
+	.filter(|tag| matches!(tag, SNoteSection::Tag(_)) && text[tag.span()].starts_with(':'))
+	.collect();
+    let end = tags.first().map_or(line.end, |tag| tag.span().start);
+    (line.start..end, tags)
+}
+
+/// Nested items are indented to the text of the item they're in, the way
+/// CommonMark nests lists
+fn markdown_list_item(text: &str, item: &SNoteSection, indent: &str, lines: &mut Vec<String>) {
+    if let SNoteSection::ListItem{marker, task, span, inlines, children} = item {
+	let bullet = match marker.kind {
+	    ListKind::Bullet(bullet) => bullet.to_string(),
+	    ListKind::Ordered(number) => format!("{}.", number),
+	};
+	let checkbox = match task {
+	    Some(task) if task.kind == TaskKind::Checkbox => if task.done { "[x] " } else { "[ ] " },
+	    _ => "",
+	};
+	let start = task.as_ref().map_or(marker.span.end, |task| task.span.end);
+	let content = markdown_inlines(text, start..span.end, inlines);
+	let content = escape_block_start(content.trim());
+	lines.push(format!("{}{} {}{}", indent, bullet, checkbox, content).trim_end().to_string());
+	let nested = format!("{}{}", indent, " ".repeat(bullet.len() + 1));
+	for child in children {
+	    markdown_list_item(text, child, &nested, lines);
+	}
+    }
+}
+
+fn ends_with_blank_line(text: &str) -> bool {
+    let mut lines = text.lines().rev();
+    lines.next().is_some_and(|last| last.trim().is_empty()) && lines.next().is_some()
+}
+
+fn markdown_table(text: &str, rows: &[TableRow]) -> String {
+    let cells = |row: &TableRow| row.cells.iter()
+	.map(|cell| markdown_cell(text, cell.clone()))
+	.collect::<Vec<_>>();
+    let columns = rows.iter().map(|row| row.cells.len()).max().unwrap_or(0).max(1);
+    let line = |cells: Vec<String>| {
+	let padded: Vec<_> = (0..columns).map(|i| cells.get(i).cloned().unwrap_or_default()).collect();
+	format!("| {} |", padded.join(" | "))
+    };
+    // Markdown tables need a header, the rows above the first separator
+    let separator = rows.iter().position(|row| row.separator);
+    let header = match separator {
+	Some(separator) if separator > 0 => separator,
+	_ => 1,
+    };
+    let alignment: Vec<_> = separator
+	.map(|at| rows[at].cells.iter().map(|cell| text[cell.clone()].trim()).collect())
+	.unwrap_or_default();
+    let mut lines: Vec<_> = rows[..header.min(rows.len())].iter().map(|row| line(cells(row))).collect();
+    lines.push(line((0..columns)
+		    .map(|i| match alignment.get(i).copied() {
+			Some(cell) if cell.starts_with(':') && cell.ends_with(':') && cell.len() > 1 => ":---:",
+			Some(cell) if cell.starts_with(':') => ":---",
+			Some(cell) if cell.ends_with(':') => "---:",
+			_ => "---",
+		    }.to_string())
+		    .collect()));
+    lines.extend(rows.iter().skip(header).filter(|row| !row.separator).map(|row| line(cells(row))));
+    lines.join("\n")
+}
+
+/// A table cell's text in Markdown, its inlines are parsed here since
+/// they aren't with the table
+fn markdown_cell(text: &str, cell: Range<usize>) -> String {
+    let content = &text[cell.clone()];
+    let start = cell.start + content.len() - content.trim_start().len();
+    let range = start..start + content.trim().len();
+    let inlines = parse_inlines(&text[range.clone()], range.start);
+    markdown_inlines(text, range, &inlines).replace('|', "\\|")
+}
+
+/// The text of `range` with its markup, links and urls in Markdown
+fn markdown_inlines(text: &str, range: Range<usize>, inlines: &[SNoteSection]) -> String {
+    let mut markdown = String::new();
+    let mut at = range.start;
+    for inline in inlines.iter().filter(|inline| range.contains(&inline.span().start)) {
+	let span = inline.span();
+	markdown.push_str(&escape_markdown(&text[at..span.start]));
+	let content = &text[inline.content_span()];
+	match inline {
+	    SNoteSection::Markup(markup, _) => {
+		let content = escape_markdown(content);
+		markdown.push_str(&match markup {
+		    Markup::Bold => format!("**{}**", content),
+		    Markup::Italic => format!("*{}*", content),
+		    Markup::Code => format!("`{}`", &text[inline.content_span()]),
+		    Markup::Strike => format!("~~{}~~", content),
+		    Markup::Underline => format!("<u>{}</u>", content),
+		});
+	    },
+	    SNoteSection::Link(_) => {
+		let name = content.trim();
+		markdown.push_str(&format!("[{}]({}.md)", escape_markdown(name), encode_link_target(name)));
+	    },
+	    SNoteSection::Url(_) => markdown.push_str(&format!("<{}>", content)),
+	    _ => markdown.push_str(&escape_markdown(&text[span.clone()])),
+	}
+	at = span.end;
+    }
+    markdown.push_str(&escape_markdown(&text[at..range.end]));
+    markdown
+}
+
+/// Percent encodes what would end a link's target early or make it a
+/// fragment
+fn encode_link_target(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for c in name.chars() {
+	match c {
+	    ' ' | '%' | '(' | ')' | '<' | '>' | '#' | '[' | ']' => encoded.push_str(&format!("%{:02X}", c as u32)),
+	    _ => encoded.push(c),
+	}
+    }
+    encoded
+}
+
+/// Escapes what Markdown would read as the start of another block at the
+/// start of a line, a heading, a list, a quote or a heading's underline
+fn escape_block_start(line: &str) -> String {
+    let line = line.trim_start();
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    match line[digits..].chars().next() {
+	Some('.' | ')') if digits > 0 => format!("{}\\{}", &line[..digits], &line[digits..]),
+	Some('#' | '-' | '+' | '=' | '>') if digits == 0 => format!("\\{}", line),
+	_ => line.to_string(),
+    }
+}
+
+/// Escapes what Markdown would read as markup in plain text, `_` only where
+/// it could start or end emphasis
+fn escape_markdown(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut escaped = String::with_capacity(text.len());
+    for (i, &c) in chars.iter().enumerate() {
+	let in_word = |at: Option<&char>| at.is_some_and(|c| c.is_alphanumeric());
+	let escape = match c {
+	    '\\' | '*' | '`' | '[' | ']' | '<' | '~' => true,
+	    '_' => !(in_word(i.checked_sub(1).and_then(|before| chars.get(before))) && in_word(chars.get(i + 1))),
+	    _ => false,
+	};
+	if escape {
+	    escaped.push('\\');
+	}
+	escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    use eframe::egui;
+
+    use crate::snote::SNote;
+
+    use super::{ExportArgs, Format, export_names, to_html, to_markdown};
+
+    fn markdown(note: &str) -> String {
+	to_markdown(&SNote::from_str(note).unwrap())
+    }
+
+    #[test]
+    fn notes_become_markdown() {
+	let note = "* TODO Plan #now :work:\n:PROPERTIES:\n:source: me\n:END:\nsee [[other note]] at https://x.org, 2*3\n\n\
+		    ** Steps\n1. /first/\n   - [x] ~done~\n2. snake_case `a*b`\n\n```sh\necho hi\n```\n\n| a | b |\n|---+--:|\n| 1 | 2 |\n";
+	assert_eq!(
+	    "---\nsource: \"me\"\n---\n\n# TODO Plan #now #work\n\n\
+	     see [other note](other%20note.md) at <https://x.org>, 2\\*3\n\n\
+	     ## Steps\n\n1. *first*\n   - [x] ~~done~~\n2. snake_case `a*b`\n\n```sh\necho hi\n```\n\n\
+	     | a | b |\n| --- | ---: |\n| 1 | 2 |\n",
+	    markdown(note)
+	);
+    }
+
+    #[test]
+    fn tables_without_a_header_use_their_first_row() {
+	assert_eq!("| x | y |\n| --- | --- |\n| z |  |\n", markdown("|x|y|\n|z|"));
+    }
+
+    #[test]
+    fn notes_become_html_styled_like_the_editor() {
+	let style = egui::Style{visuals: egui::Visuals::light(), ..Default::default()};
+	let html = to_html(&SNote::from_str("* a <b>\nx & *y*\n").unwrap(), "a & b", &style);
+	assert!(html.contains("<title>a &amp; b</title>"));
+	assert!(html.contains("a &lt;b&gt;"));
+	assert!(html.contains("x &amp; </span>"));
+	let headline = html.split("<span").find(|span| span.contains("a &lt;b&gt;")).unwrap();
+	let body_size = egui::TextStyle::Body.resolve(&style).size;
+	assert!(headline.contains("text-decoration:underline") && !headline.contains(&format!("font-size:{}px", body_size)));
+	assert!(html.contains(&format!("background:{}", super::css_color(style.visuals.extreme_bg_color))));
+    }
+
+    #[test]
+    fn paragraphs_dont_start_other_blocks() {
+	assert_eq!(
+	    "\\# not a heading\n1986\\) a year\n\\+\tplus\nfoo\n\\---\n\\===\nbar\n\n- \\# item\n",
+	    markdown("# not a heading\n 1986) a year\n+\tplus\nfoo\n---\n===\nbar\n\n- # item")
+	);
+	assert_eq!("1986\\.\ta year\n", markdown("1986.\ta year"));
+    }
+
+    #[test]
+    fn markup_characters_in_text_are_escaped() {
+	assert_eq!("a \\~~~b~~\\~ c\n", markdown("a ~~b~~ c"));
+	assert_eq!("a\\_\\_b snake_case x\\_\\_\n", markdown("a__b snake_case x__"));
+	assert_eq!("[a (b) #1](a%20%28b%29%20%231.md)\n", markdown("[[a (b) #1]]"));
+    }
+
+    #[test]
+    fn table_cells_are_converted_like_text() {
+	assert_eq!(
+	    "| *b* | a\\<x> | [l](l.md) |\n| --- | --- | --- |\n",
+	    markdown("| /b/ | a<x> | [[l]] |\n|---+---+---|\n"),
+	);
+    }
+
+    #[test]
+    fn deep_headlines_and_lists_after_blank_lines() {
+	assert_eq!("###### deep\n", markdown("******* deep"));
+	assert_eq!("- a\n- b\n\n<!-- -->\n\n- c\n", markdown("- a\n- b\n\n- c\n"));
+    }
+
+    #[test]
+    fn notes_with_the_same_name_cant_be_exported_together() {
+	let names = |notes: &[&str]| export_names(&notes.iter().map(PathBuf::from).collect::<Vec<_>>());
+	assert_eq!(Ok(vec!["a".to_string(), "b".to_string()]), names(&["x/a.snot", "x/b.snot"]));
+	assert!(names(&["x/a.snot", "y/a.snot.txt"]).is_err());
+    }
+
+    #[test]
+    fn export_arguments() {
+	let args = |args: &[&str]| ExportArgs::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+	assert_eq!(
+	    Ok(ExportArgs{format: Format::Markdown, output: Some(PathBuf::from("out")), notes: vec!["a.snot".into()]}),
+	    args(&["--format", "md", "a.snot", "-o=out"])
+	);
+	assert!(args(&["--format", "pdf", "a.snot"]).is_err());
+	assert!(args(&["--format", "md"]).is_err());
+	assert_eq!(Ok(Format::Markdown), Format::from_str("Markdown"));
+	assert_eq!(Ok(Format::Html), Format::from_str("html"));
+    }
+}
@@ -0,0 +1,106 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::search::is_note;
+
+/// A directory of notes and the directories under it that have notes
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Folder {
+    pub path: PathBuf,
+    pub folders: Vec<Folder>,
+    pub notes: Vec<PathBuf>,
+}
+
+impl Folder {
+    /// Reads the tree of folders under `dir`, hidden directories are skipped
+    pub fn read(dir: impl AsRef<Path>) -> Self {
+	let path = dir.as_ref().to_path_buf();
+	let mut entries: Vec<_> = path.read_dir()
+	    .map(|d| d.filter_map(Result::ok).map(|e| e.path()).collect())
+	    .unwrap_or_default();
+	entries.sort();
+	let (dirs, files): (Vec<_>, Vec<_>) = entries.into_iter().partition(|p| p.is_dir());
+	Self{
+	    folders: dirs.into_iter()
+		.filter(|d| !is_hidden(d))
+		.map(Folder::read)
+		.collect(),
+	    notes: files.into_iter().filter(|f| is_note(f)).collect(),
+	    path,
+	}
+    }
+
+    pub fn name(&self) -> String {
+	self.path.file_name()
+	    .map(|name| name.to_string_lossy().to_string())
+	    .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    /// This folder and every folder under it
+    pub fn all_folders(&self) -> Vec<&Folder> {
+	std::iter::once(self)
+	    .chain(self.folders.iter().flat_map(Folder::all_folders))
+	    .collect()
+    }
+}
+
+pub(crate) fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Moves `note` into `folder` keeping its file name, returns its new path
+pub(crate) fn move_note(note: &Path, folder: &Path) -> io::Result<PathBuf> {
+    let name = note.file_name()
+	.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    let moved = folder.join(name);
+    if moved.exists() {
+	return Err(io::Error::new(
+	    io::ErrorKind::AlreadyExists,
+	    format!("{} already exists", moved.display()),
+	));
+    }
+    std::fs::rename(note, &moved)?;
+    Ok(moved)
+}
+
+/// Creates the folder `name` under `parent`, returns its path
+pub(crate) fn create_folder(parent: &Path, name: &str) -> io::Result<PathBuf> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(std::path::is_separator) {
+	return Err(io::Error::new(
+	    io::ErrorKind::InvalidInput,
+	    format!("invalid folder name {:?}", name),
+	));
+    }
+    let folder = parent.join(name);
+    std::fs::create_dir(&folder)?;
+    Ok(folder)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::ScratchDir;
+
+    use super::{Folder, create_folder, move_note};
+
+    #[test]
+    fn read_moves_and_create_folders() {
+	let dir = ScratchDir::new("folders");
+	std::fs::write(dir.join("a.snot"), "* a").unwrap();
+	std::fs::write(dir.join("b.snot.txt"), "* b").unwrap();
+	std::fs::write(dir.join("c.txt"), "not a note").unwrap();
+	std::fs::create_dir(dir.join(".snott")).unwrap();
+	let work = create_folder(&dir, "work").unwrap();
+	assert!(create_folder(&dir, "a/b").is_err());
+
+	let moved = move_note(&dir.join("a.snot"), &work).unwrap();
+	assert_eq!(work.join("a.snot"), moved);
+
+	let tree = Folder::read(&dir);
+	assert_eq!(vec![dir.join("b.snot.txt")], tree.notes);
+	assert_eq!(1, tree.folders.len());
+	assert_eq!("work", tree.folders[0].name());
+	assert_eq!(vec![moved], tree.folders[0].notes);
+	assert_eq!(2, tree.all_folders().len());
+    }
+}
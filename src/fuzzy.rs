@@ -0,0 +1,89 @@
+/// Matching a single char of the query
+const MATCH: i64 = 16;
+/// Matching right after the previous matched char
+const CONSECUTIVE: i64 = 12;
+/// Matching the first char of a word, e.g. after a space or a `-`
+const WORD_START: i64 = 10;
+/// The most a match loses for starting late in the text
+const MAX_LEADING_PENALTY: i64 = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FuzzyMatch {
+    pub score: i64,
+    /// char indices of the matched chars in the text
+    pub positions: Vec<usize>,
+}
+
+/// Matches `query` against `text` as a subsequence ignoring case, the chars
+/// of the query don't have to be adjacent but adjacent ones score higher
+pub(crate) fn fuzzy_match(query: &str, text: &str) -> Option<FuzzyMatch> {
+    let lower = |s: &str| s.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect::<Vec<_>>();
+    let (query, chars) = (lower(query), text.chars().collect::<Vec<_>>());
+    let lowered = lower(text);
+    // a forward pass finds where the query is first matched completely
+    let mut end = None;
+    let mut rest = lowered.iter().enumerate();
+    for q in &query {
+	end = Some(rest.find(|(_, c)| *c == q)?.0);
+    }
+    let end = match end {
+	Some(end) => end,
+	None => return Some(FuzzyMatch{score: 0, positions: vec![]}),
+    };
+    // matching backwards from there tightens the match to the latest start
+    let mut positions = Vec::with_capacity(query.len());
+    let mut before = lowered[..=end].iter().enumerate().rev();
+    for q in query.iter().rev() {
+	positions.push(before.find(|(_, c)| *c == q)?.0);
+    }
+    positions.reverse();
+    Some(FuzzyMatch{score: score(&chars, &positions), positions})
+}
+
+fn score(text: &[char], positions: &[usize]) -> i64 {
+    let is_word_start = |i: usize| i == 0 || {
+	let (prev, c) = (text[i - 1], text[i]);
+	!prev.is_alphanumeric() || (prev.is_lowercase() && c.is_uppercase())
+    };
+    let leading = (positions[0] as i64).min(MAX_LEADING_PENALTY);
+    positions.iter()
+	.enumerate()
+	.map(|(n, &i)| {
+	    let gap = n.checked_sub(1).map(|prev| i - positions[prev] - 1);
+	    MATCH
+		+ if gap == Some(0) { CONSECUTIVE } else { 0 }
+		+ if is_word_start(i) { WORD_START } else { 0 }
+		- gap.unwrap_or(0) as i64
+	})
+	.sum::<i64>() - leading
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzzy_match;
+
+    fn score(query: &str, text: &str) -> i64 {
+	fuzzy_match(query, text).expect("should match").score
+    }
+
+    #[test]
+    fn query_is_a_subsequence_ignoring_case() {
+	assert_eq!(vec![0, 1, 3], fuzzy_match("SNT", "snott").unwrap().positions);
+	assert_eq!(vec![1, 2], fuzzy_match("äb", "xÄb").unwrap().positions);
+	assert_eq!(None, fuzzy_match("tons", "snott"));
+	assert_eq!(Some(vec![]), fuzzy_match("", "snott").map(|m| m.positions));
+    }
+
+    #[test]
+    fn tight_matches_are_found() {
+	assert_eq!(vec![3, 4], fuzzy_match("ab", "a xab").unwrap().positions);
+    }
+
+    #[test]
+    fn better_matches_score_higher() {
+	assert!(score("note", "my notes") > score("note", "n o t e"));
+	assert!(score("gr", "shopping groceries") > score("gr", "shopping agreement"));
+	assert!(score("qs", "QuickSnote") > score("qs", "quicks"));
+	assert!(score("todo", "todo list") > score("todo", "my long todo list"));
+    }
+}
@@ -0,0 +1,389 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::search::{note_files, note_name};
+use crate::snote::{SNote, SNoteSection};
+use crate::stable_hash::stable_hash;
+
+/// Bumped whenever `IndexedNote` changes, older indexes are built again
+const INDEX_VERSION: u32 = 1;
+
+/// An inverted index of the notes in a directory, persisted in the user's
+/// cache directory so opening Snotter doesn't have to read every note again
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct NoteIndex {
+    #[serde(default)]
+    version: u32,
+    #[serde(skip)]
+    snots_dir: PathBuf,
+    /// where the index is saved
+    #[serde(skip)]
+    file: PathBuf,
+    notes: BTreeMap<PathBuf, IndexedNote>,
+    /// every lowercase word and the notes it appears in
+    terms: BTreeMap<String, BTreeSet<PathBuf>>,
+    /// whether notes were reindexed since the index was saved
+    #[serde(skip)]
+    unsaved: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct IndexedNote {
+    pub modified: SystemTime,
+    pub headlines: Vec<String>,
+    pub terms: BTreeSet<String>,
+    /// the note's tags, lowercased
+    pub tags: BTreeSet<String>,
+    /// the names of the notes it links to, lowercased
+    pub links: BTreeSet<String>,
+    /// the note's properties written as text, by their lowercase keys
+    pub properties: BTreeMap<String, String>,
+}
+
+impl NoteIndex {
+    /// Loads the index saved for `snots_dir` and brings it up to date, what
+    /// changed is saved with the next changes
+    pub fn open(snots_dir: impl AsRef<Path>) -> Self {
+	Self::open_at(index_file(snots_dir.as_ref()), snots_dir)
+    }
+
+    /// Like `open`, with the index saved in `file` instead of the cache
+    pub fn open_at(file: PathBuf, snots_dir: impl AsRef<Path>) -> Self {
+	let snots_dir = snots_dir.as_ref().to_path_buf();
+	let saved = std::fs::read_to_string(&file)
+	    .ok()
+	    .and_then(|json| serde_json::from_str::<NoteIndex>(&json).ok())
+	    .filter(|saved| saved.version == INDEX_VERSION)
+	    .unwrap_or_default();
+	let mut index = Self{snots_dir, file, version: INDEX_VERSION, ..saved};
+	index.reindex_all();
+	index
+    }
+
+    /// Reindexes the notes that changed since they were indexed and saves
+    /// the index if any did
+    pub fn refresh(&mut self) -> Result<(), Box<dyn Error>> {
+	self.reindex_all();
+	self.save_changes()
+    }
+
+    /// Forgets deleted notes and reindexes changed ones, only the files'
+    /// modification times are read for the rest
+    fn reindex_all(&mut self) {
+	let files: HashSet<PathBuf> = note_files(&self.snots_dir).into_iter().collect();
+	let deleted: Vec<_> = self.notes.keys()
+	    .filter(|path| !files.contains(*path))
+	    .cloned()
+	    .collect();
+	self.unsaved |= !deleted.is_empty();
+	deleted.iter().for_each(|path| self.remove(path));
+	for file in files {
+	    self.update(&file);
+	}
+    }
+
+    /// Saves the index if notes were reindexed since it last was, updates
+    /// are only kept in memory until then
+    pub fn save_changes(&mut self) -> Result<(), Box<dyn Error>> {
+	if self.unsaved {
+	    self.save()?;
+	    self.unsaved = false;
+	}
+	Ok(())
+    }
+
+    /// Reindexes a single note if it changed, returns whether it did
+    pub fn update(&mut self, path: &Path) -> bool {
+	let changed = self.reindex(path);
+	self.unsaved |= changed;
+	changed
+    }
+
+    fn reindex(&mut self, path: &Path) -> bool {
+	let modified = match std::fs::metadata(path).and_then(|m| m.modified()) {
+	    Ok(modified) => modified,
+	    Err(_) => {
+		let known = self.notes.contains_key(path);
+		self.remove(path);
+		return known;
+	    },
+	};
+	if self.notes.get(path).is_some_and(|note| note.modified == modified) {
+	    return false;
+	}
+	match std::fs::read_to_string(path) {
+	    Ok(content) => self.insert(path.to_path_buf(), IndexedNote::new(&content, modified)),
+	    Err(_) => self.remove(path),
+	}
+	true
+    }
+
+    pub fn notes(&self) -> impl Iterator<Item = (&Path, &IndexedNote)> {
+	self.notes.iter().map(|(path, note)| (path.as_path(), note))
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&IndexedNote> {
+	self.notes.get(path)
+    }
+
+    /// The note a `[[name]]` link points to: the note with that file name,
+    /// or else the one with that title or alias, ignoring case
+    pub fn resolve(&self, name: &str) -> Option<&Path> {
+	let name = name.trim().to_lowercase();
+	self.notes()
+	    .find(|(path, _)| note_name(path).to_lowercase() == name)
+	    .or_else(|| self.notes().find(|(_, note)| note.title().is_some_and(|title| title == name)))
+	    .or_else(|| self.notes().find(|(_, note)| note.aliases().any(|alias| alias == name)))
+	    .map(|(path, _)| path)
+    }
+
+    /// The notes linking to the note at `path` by its name, title or aliases
+    pub fn backlinks(&self, path: &Path) -> Vec<&Path> {
+	let title = self.get(path).and_then(IndexedNote::title);
+	let aliases = self.get(path).into_iter().flat_map(IndexedNote::aliases);
+	let names: Vec<_> = std::iter::once(note_name(path).to_lowercase()).chain(title).chain(aliases).collect();
+	self.notes()
+	    .filter(|(other, note)| *other != path && note.links.iter().any(|link| names.contains(link)))
+	    .map(|(other, _)| other)
+	    .collect()
+    }
+
+    /// What links to the notes can be written as: their titles, or their
+    /// names when they have none, most recently changed first
+    pub fn link_targets(&self) -> Vec<String> {
+	let mut notes: Vec<_> = self.notes().collect();
+	notes.sort_by_key(|(_, note)| Reverse(note.modified));
+	let mut targets: Vec<String> = vec![];
+	for (path, note) in notes {
+	    let target = note.headlines.first()
+		.filter(|title| !title.is_empty() && !title.contains(['[', ']']))
+		.cloned()
+		.unwrap_or_else(|| note_name(path));
+	    if !targets.contains(&target) {
+		targets.push(target);
+	    }
+	}
+	targets
+    }
+
+    /// The notes containing a word starting with each of the words in `query`
+    pub fn candidates(&self, query: &str) -> BTreeSet<&Path> {
+	let mut words = terms(query).into_iter();
+	let first = match words.next() {
+	    Some(word) => self.with_prefix(&word),
+	    None => return Default::default(),
+	};
+	words.fold(first, |found, word| {
+	    let also = self.with_prefix(&word);
+	    found.intersection(&also).copied().collect()
+	})
+    }
+
+    fn with_prefix(&self, prefix: &str) -> BTreeSet<&Path> {
+	self.terms.range(prefix.to_string()..)
+	    .take_while(|(term, _)| term.starts_with(prefix))
+	    .flat_map(|(_, paths)| paths.iter().map(PathBuf::as_path))
+	    .collect()
+    }
+
+    fn insert(&mut self, path: PathBuf, note: IndexedNote) {
+	self.remove(&path);
+	for term in &note.terms {
+	    self.terms.entry(term.clone()).or_default().insert(path.clone());
+	}
+	self.notes.insert(path, note);
+    }
+
+    fn remove(&mut self, path: &Path) {
+	if let Some(note) = self.notes.remove(path) {
+	    for term in note.terms {
+		if let Some(paths) = self.terms.get_mut(&term) {
+		    paths.remove(path);
+		    if paths.is_empty() {
+			self.terms.remove(&term);
+		    }
+		}
+	    }
+	}
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+	if let Some(dir) = self.file.parent() {
+	    std::fs::create_dir_all(dir)?;
+	}
+	let tmp = self.file.with_extension("json.tmp");
+	std::fs::write(&tmp, serde_json::to_string(self)?)?;
+	std::fs::rename(tmp, &self.file)?;
+	Ok(())
+    }
+}
+
+impl IndexedNote {
+    pub fn new(content: &str, modified: SystemTime) -> Self {
+	let note = SNote::from_str(content).unwrap_or_else(|_| SNote::new());
+	Self{
+	    modified,
+	    headlines: headlines(content, &note.sections()),
+	    terms: terms(content),
+	    tags: note.tags().iter().map(|tag| tag.to_lowercase()).collect(),
+	    links: note.links().iter().map(|link| link.to_lowercase()).collect(),
+	    properties: note.properties().to_map(),
+	}
+    }
+
+    /// The first headline, lowercased the way links are compared
+    fn title(&self) -> Option<String> {
+	self.headlines.first().map(|title| title.to_lowercase())
+    }
+
+    /// The other names of the note, lowercased
+    fn aliases(&self) -> impl Iterator<Item = String> + '_ {
+	self.properties.get("aliases")
+	    .into_iter()
+	    .flat_map(|aliases| aliases.split(','))
+	    .map(|alias| alias.trim().to_lowercase())
+    }
+}
+
+fn headlines(content: &str, sections: &[SNoteSection]) -> Vec<String> {
+    sections.iter()
+	.filter(|section| section.level() > 0)
+	.flat_map(|headline| {
+	    let span = headline.content_span();
+	    // the `:tags:` at the end aren't part of the title
+	    let end = headline.inlines().iter()
+		.find(|tag| matches!(tag, SNoteSection::Tag(_)) && content[tag.span()].starts_with(':'))
+		.map_or(span.end, |tag| tag.span().start);
+	    let title = content[span.start..end].trim().to_string();
+	    std::iter::once(title).chain(headlines(content, headline.children()))
+	})
+	.collect()
+}
+
+/// The lowercase words of `text`
+pub(crate) fn terms(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+	.filter(|word| !word.is_empty())
+	.map(str::to_lowercase)
+	.collect()
+}
+
+/// Every notes directory gets its own index file in the cache directory
+fn index_file(snots_dir: &Path) -> PathBuf {
+    dirs::cache_dir()
+	.unwrap_or_else(std::env::temp_dir)
+	.join("snott")
+	.join(format!("index-{:016x}.json", stable_hash(snots_dir.as_os_str().as_encoded_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::SystemTime;
+
+    use crate::test_util::ScratchDir;
+
+    use super::{IndexedNote, NoteIndex, terms};
+
+    fn index_of(notes: &[(&str, &str)]) -> NoteIndex {
+	let mut index = NoteIndex::default();
+	for (path, content) in notes {
+	    index.insert(path.into(), IndexedNote::new(content, SystemTime::UNIX_EPOCH));
+	}
+	index
+    }
+
+    #[test]
+    fn words_are_lowercased_terms() {
+	let found: Vec<_> = terms("Hello, *world*! hello_again").into_iter().collect();
+	assert_eq!(vec!["again", "hello", "world"], found);
+    }
+
+    #[test]
+    fn headlines_are_indexed() {
+	let note = IndexedNote::new("* TODO groceries\nmilk\n** for monday\n", SystemTime::UNIX_EPOCH);
+	assert_eq!(vec!["TODO groceries", "for monday"], note.headlines);
+    }
+
+    #[test]
+    fn tags_are_indexed_lowercased() {
+	let note = IndexedNote::new("* groceries :Home:\nfor #monday", SystemTime::UNIX_EPOCH);
+	assert_eq!(vec!["home", "monday"], note.tags.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn links_are_resolved_by_name_or_title() {
+	let index = index_of(&[
+	    ("dir/2022-01-01.snot.txt", "* Project X :work:\nsee [[ideas]]"),
+	    ("ideas.snot", "* Ideas\nfor [[project x]]"),
+	    ("other.snot", "* Other\n[[2022-01-01]] and [[nowhere]]"),
+	]);
+	assert_eq!(Some(Path::new("dir/2022-01-01.snot.txt")), index.resolve("Project X"));
+	assert_eq!(Some(Path::new("ideas.snot")), index.resolve("IDEAS"));
+	assert_eq!(None, index.resolve("nowhere"));
+	assert_eq!(
+	    vec![Path::new("ideas.snot"), Path::new("other.snot")],
+	    index.backlinks(Path::new("dir/2022-01-01.snot.txt"))
+	);
+    }
+
+    #[test]
+    fn links_resolve_by_alias() {
+	let index = index_of(&[
+	    ("a.snot", "* A\n:PROPERTIES:\n:aliases: Alpha, first one\n:END:\n"),
+	    ("b.snot", "see [[first ONE]]"),
+	]);
+	assert_eq!(Some(Path::new("a.snot")), index.resolve("alpha"));
+	assert_eq!(vec![Path::new("b.snot")], index.backlinks(Path::new("a.snot")));
+	assert_eq!(Some("Alpha, first one"), index.get(Path::new("a.snot")).and_then(|note| note.properties.get("aliases")).map(String::as_str));
+    }
+
+    #[test]
+    fn updates_are_saved_with_the_changes() {
+	let dir = ScratchDir::new("index");
+	let (note, file) = (dir.join("a.snot"), dir.join(".snott").join("index.json"));
+	std::fs::write(&note, "first").unwrap();
+	let mut index = NoteIndex::open_at(file.clone(), &dir);
+	let saved = || std::fs::read_to_string(&file).unwrap();
+	assert!(!file.exists());
+	index.refresh().unwrap();
+	assert!(saved().contains("first"));
+	std::fs::write(&note, "second").unwrap();
+	std::fs::File::options().append(true).open(&note).unwrap()
+	    .set_modified(SystemTime::UNIX_EPOCH).unwrap();
+	assert!(index.update(&note));
+	assert!(!saved().contains("second"));
+	index.save_changes().unwrap();
+	assert!(saved().contains("second"));
+    }
+
+    #[test]
+    fn candidates_match_every_word_prefix() {
+	let index = index_of(&[
+	    ("a.snot", "buy eggs and milk"),
+	    ("b.snot", "eggplant recipe"),
+	    ("c.snot", "milk the cow"),
+	]);
+	let paths = |query| index.candidates(query).into_iter().collect::<Vec<_>>();
+	assert_eq!(vec![Path::new("a.snot"), Path::new("b.snot")], paths("egg"));
+	assert_eq!(vec![Path::new("a.snot")], paths("EGG mil"));
+	assert!(paths("nothing").is_empty());
+	assert!(paths("").is_empty());
+    }
+
+    #[test]
+    fn reindexing_a_note_forgets_its_old_terms() {
+	let mut index = index_of(&[("a.snot", "old words")]);
+	index.insert("a.snot".into(), IndexedNote::new("new", SystemTime::UNIX_EPOCH));
+	assert!(index.candidates("old").is_empty());
+	assert_eq!(1, index.candidates("new").len());
+	index.remove(Path::new("a.snot"));
+	assert!(index.terms.is_empty());
+    }
+}
@@ -5,8 +5,22 @@
 pub(crate) mod custom_window;
 pub mod app;
 pub(crate) mod autocomplete_popup;
+pub(crate) mod fuzzy;
+pub(crate) mod search;
+pub(crate) mod index;
+pub(crate) mod folders;
+pub(crate) mod tabs;
+pub(crate) mod saving;
+pub(crate) mod diff;
+pub(crate) mod watcher;
+pub(crate) mod snapshots;
+pub(crate) mod stable_hash;
 pub mod snote;
 pub mod quick_snote;
+pub mod settings;
+pub mod export;
+#[cfg(test)]
+mod test_util;
 // pub mod quick_snote_main;
 // mod snote_parser;
 // mod snote_hightlighter;
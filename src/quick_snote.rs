@@ -4,12 +4,12 @@ use chrono::{Local, DateTime};
 
 use eframe::egui;
 
-use crate::snote::snote_widget;
+use crate::snote::{snote_widget, SNote, TIME_FORMAT};
 
 #[derive(Debug)]
 pub struct QuickSnote {
     creation_time: DateTime<Local>,
-    text: String,
+    text: SNote,
     approved: bool,
     pub sync_dir: PathBuf,
     pub timestamp_format: String,
@@ -19,7 +19,7 @@ impl QuickSnote{
     pub fn new(sync_dir: impl AsRef<Path>) -> Self{
         Self{
             creation_time: Local::now(),
-            text: Default::default(),
+            text: SNote::new(),
             sync_dir: sync_dir.as_ref().into(),
             approved: false,
             timestamp_format: Default::default(),
@@ -31,16 +31,13 @@ impl QuickSnote{
             ..self
         }
     }
-
-	}
-    }
 }
 impl Default for QuickSnote{
     fn default() -> Self {
         Self {
             creation_time: Local::now(),
             sync_dir: Default::default(),
-            text: Default::default(),
+            text: SNote::new(),
             approved: false,
             timestamp_format: Default::default(),
         }
@@ -66,15 +63,22 @@ impl eframe::App for QuickSnote {
     }
 
     fn on_exit(&mut self, _gl: &eframe::glow::Context) {
-	// TODO: remove the .txt suffix when I get android app support
-        let name_format = format!("{}.snot.txt", self.timestamp_format);
-        let file_name = self.creation_time.format(&name_format);
-        let full_path = self.sync_dir.join(PathBuf::from(&file_name.to_string()));
+        let file_name = note_file_name(&self.creation_time, &self.timestamp_format);
+        let full_path = self.sync_dir.join(PathBuf::from(&file_name));
         if self.approved {
-            fs::write(&full_path, &self.text)
+	    let created = self.creation_time.format(TIME_FORMAT).to_string();
+	    self.text.set_property("created", &created);
+            fs::write(&full_path, &self.text.raw_content)
                 .unwrap_or_else(
                     |e|panic!("failed to save file {}: {}", &full_path.display(), e)
                 )
         }
     }
 }
+
+/// The file name of a note created at `time`
+pub(crate) fn note_file_name(time: &DateTime<Local>, timestamp_format: &str) -> String {
+    // TODO: remove the .txt suffix when I get android app support
+    let name_format = format!("{}.snot.txt", timestamp_format);
+    time.format(&name_format).to_string()
+}
@@ -4,10 +4,16 @@ use std::path::PathBuf;
 
 use eframe::egui;
 use snote2::quick_snote::QuickSnote;
-use config::Config;
+use snote2::settings::build_config;
 
 fn main() {
-    let settings = build_config();
+    let settings = match build_config() {
+	Ok(settings) => settings,
+	Err(e) => {
+	    eprintln!("can't read the settings: {}", e);
+	    std::process::exit(1);
+	},
+    };
     let options = eframe::NativeOptions {
         decorated: false,
         transparent: true,
@@ -38,21 +44,3 @@ fn main() {
         }),
     );
 }
-
-fn build_config() -> config::Config{
-    let home_dir = dirs::home_dir().unwrap_or_default();
-    let config_file = home_dir
-	.join(".config")
-	.join("snott")
-	.join("config")
-	.into();
-    Config::builder()
-        .add_source::<config::File<_,_>>(config_file)
-        .add_source(config::Environment::with_prefix("SNOTT"))
-        .set_default("sync_dir", home_dir.display().to_string()).unwrap()
-        .set_default("timestamp_format", "%Y-%m-%d_%H-%M-%S").unwrap()
-        .set_default("body_font_size", "20").unwrap()
-        .set_default("headline_font_size", "40").unwrap()
-        .build()
-        .unwrap()
-}
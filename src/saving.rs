@@ -0,0 +1,265 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::diff::merge3;
+
+/// How long a note has to be left alone after an edit before it's saved
+pub(crate) const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// A version of a note's file, to tell when something else changed it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DiskVersion {
+    modified: Option<SystemTime>,
+    hash: u64,
+}
+
+/// The file's content when it changed on disk while the note was edited
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Conflict {
+    pub theirs: String,
+    version: DiskVersion,
+}
+
+/// How a note relates to its file: what the file had when the note was
+/// loaded or last saved, and the edits made since then
+#[derive(Debug, Default)]
+pub(crate) struct SaveState {
+    /// the content of the file as of `version`
+    base: String,
+    /// `None` if the file didn't exist or couldn't be read
+    version: Option<DiskVersion>,
+    /// when the note was last edited, while it has unsaved edits
+    edited: Option<Instant>,
+    pub conflict: Option<Conflict>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Saved {
+    Written,
+    /// the file changed since it was loaded, nothing was written
+    Conflict,
+}
+
+impl DiskVersion {
+    fn of(path: &Path, content: &str) -> Self {
+	Self{
+	    modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+	    hash: hash(content),
+	}
+    }
+}
+
+#[cfg(test)]
+impl Conflict {
+    pub fn new(theirs: &str) -> Self {
+	Self{theirs: theirs.to_string(), version: DiskVersion{modified: None, hash: hash(theirs)}}
+    }
+}
+
+impl SaveState {
+    /// Reads the note at `path`
+    pub fn load(path: &Path) -> io::Result<(String, Self)> {
+	let content = std::fs::read_to_string(path)?;
+	let state = Self::loaded(path, &content);
+	Ok((content, state))
+    }
+
+    fn loaded(path: &Path, content: &str) -> Self {
+	Self{
+	    base: content.to_string(),
+	    version: Some(DiskVersion::of(path, content)),
+	    ..Default::default()
+	}
+    }
+
+    /// What the file had when the note was loaded or last saved
+    pub fn base(&self) -> &str {
+	&self.base
+    }
+
+    pub fn edited(&mut self) {
+	self.edited = Some(Instant::now());
+    }
+
+    pub fn is_dirty(&self) -> bool {
+	self.edited.is_some()
+    }
+
+    /// Whether the last edit was long enough ago for the note to be saved
+    pub fn is_due(&self) -> bool {
+	self.conflict.is_none() && self.edited.is_some_and(|at| at.elapsed() >= SAVE_DELAY)
+    }
+
+    /// Writes `content` to `path` unless the file changed since it was loaded
+    pub fn save(&mut self, path: &Path, content: &str) -> io::Result<Saved> {
+	if let Some(conflict) = self.changed_on_disk(path)? {
+	    self.conflict = Some(conflict);
+	    return Ok(Saved::Conflict);
+	}
+	self.overwrite(path, content)?;
+	Ok(Saved::Written)
+    }
+
+    /// Writes `content` to `path` whatever is in the file now
+    pub fn overwrite(&mut self, path: &Path, content: &str) -> io::Result<()> {
+	write_atomically(path, content)?;
+	*self = Self::loaded(path, content);
+	Ok(())
+    }
+
+    /// Drops `mine` for the content the file changed to, returns it
+    pub fn reload(&mut self) -> Option<String> {
+	let Conflict{theirs, version} = self.conflict.take()?;
+	*self = Self{base: theirs.clone(), version: Some(version), ..Default::default()};
+	Some(theirs)
+    }
+
+    /// Merges `mine` with the content the file changed to, returns the
+    /// merged text which still has to be saved
+    pub fn merge(&mut self, mine: &str) -> Option<String> {
+	let Conflict{theirs, version} = self.conflict.take()?;
+	let merged = merge3(&self.base, mine, &theirs);
+	*self = Self{base: theirs, version: Some(version), ..Default::default()};
+	self.edited();
+	Some(merged)
+    }
+
+    /// Checks the file after something changed it: a note without edits
+    /// takes the new content, which is returned, a note with edits gets a
+    /// conflict instead
+    pub fn disk_changed(&mut self, path: &Path) -> io::Result<Option<String>> {
+	let conflict = match self.changed_on_disk(path)? {
+	    Some(conflict) => conflict,
+	    None => return Ok(None),
+	};
+	self.conflict = Some(conflict);
+	match self.is_dirty() {
+	    true => Ok(None),
+	    false => Ok(self.reload()),
+	}
+    }
+
+    fn changed_on_disk(&self, path: &Path) -> io::Result<Option<Conflict>> {
+	let modified = match std::fs::metadata(path) {
+	    Ok(metadata) => metadata.modified().ok(),
+	    // a file deleted since it was loaded is written again
+	    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+	    Err(e) => return Err(e),
+	};
+	if let Some(known) = &self.version {
+	    // a newer time alone doesn't mean the content changed, e.g. when
+	    // a sync tool touched the file
+	    if known.modified == modified {
+		return Ok(None);
+	    }
+	}
+	let theirs = std::fs::read_to_string(path)?;
+	let version = DiskVersion{modified, hash: hash(&theirs)};
+	match &self.version {
+	    Some(known) if known.hash == version.hash => Ok(None),
+	    _ => Ok(Some(Conflict{theirs, version})),
+	}
+    }
+}
+
+fn hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Writes to a temporary file next to `path` and renames it over `path`,
+/// so the file is never left half written
+pub(crate) fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let name = path.file_name()
+	.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    let tmp = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path).inspect_err(|_| {
+	std::fs::remove_file(&tmp).unwrap_or(());
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use crate::test_util::ScratchDir;
+
+    use super::{SaveState, Saved};
+
+    fn scratch_file(name: &str, content: &str) -> (ScratchDir, PathBuf) {
+	let dir = ScratchDir::new(name);
+	let file = dir.join("note.snot");
+	std::fs::write(&file, content).unwrap();
+	(dir, file)
+    }
+
+    /// Changes the file the way another device would, with a later mtime
+    fn write_elsewhere(file: &PathBuf, content: &str) {
+	std::fs::write(file, content).unwrap();
+	let later = SystemTime::now() + Duration::from_secs(10);
+	std::fs::File::options().write(true).open(file).unwrap().set_modified(later).unwrap();
+    }
+
+    #[test]
+    fn saving_an_unchanged_file() {
+	let (_dir, file) = scratch_file("save", "* a");
+	let (_, mut state) = SaveState::load(&file).unwrap();
+	state.edited();
+	assert!(state.is_dirty());
+	assert_eq!(Saved::Written, state.save(&file, "* b").unwrap());
+	assert!(!state.is_dirty());
+	assert_eq!("* b", std::fs::read_to_string(&file).unwrap());
+	assert_eq!(Saved::Written, state.save(&file, "* c").unwrap());
+    }
+
+    #[test]
+    fn touching_a_file_is_not_a_conflict() {
+	let (_dir, file) = scratch_file("touch", "* a");
+	let (_, mut state) = SaveState::load(&file).unwrap();
+	write_elsewhere(&file, "* a");
+	assert_eq!(Saved::Written, state.save(&file, "* b").unwrap());
+    }
+
+    #[test]
+    fn resolving_conflicts() {
+	let (_dir, file) = scratch_file("conflict", "* a\none\ntwo");
+	let (_, mut state) = SaveState::load(&file).unwrap();
+	write_elsewhere(&file, "* a\none\ntwo\nthree");
+	assert_eq!(Saved::Conflict, state.save(&file, "* a\nONE\ntwo").unwrap());
+	assert_eq!("* a\none\ntwo\nthree", std::fs::read_to_string(&file).unwrap());
+	assert!(!state.is_due());
+
+	let merged = state.merge("* a\nONE\ntwo").unwrap();
+	assert_eq!("* a\nONE\ntwo\nthree", merged);
+	assert!(state.is_dirty());
+	assert_eq!(Saved::Written, state.save(&file, &merged).unwrap());
+
+	write_elsewhere(&file, "theirs");
+	assert_eq!(Saved::Conflict, state.save(&file, "mine").unwrap());
+	assert_eq!(Some("theirs".to_string()), state.reload());
+	assert!(state.conflict.is_none() && !state.is_dirty());
+    }
+
+    #[test]
+    fn changes_on_disk_reload_notes_without_edits() {
+	let (_dir, file) = scratch_file("reload", "* a");
+	let (_, mut state) = SaveState::load(&file).unwrap();
+	assert_eq!(None, state.disk_changed(&file).unwrap());
+	write_elsewhere(&file, "* b");
+	assert_eq!(Some("* b".to_string()), state.disk_changed(&file).unwrap());
+	assert!(state.conflict.is_none());
+
+	state.edited();
+	write_elsewhere(&file, "* c");
+	assert_eq!(None, state.disk_changed(&file).unwrap());
+	assert_eq!(Some("* c"), state.conflict.as_ref().map(|c| c.theirs.as_str()));
+    }
+}
@@ -0,0 +1,317 @@
+use std::cmp::Reverse;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::folders::is_hidden;
+use crate::index::{IndexedNote, NoteIndex};
+
+/// How many characters of a matching line are shown around the match
+const CONTEXT_CHARS: usize = 30;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum SearchHit {
+    /// The note itself, matched by its name and title
+    Name{
+	path: PathBuf,
+	/// the note's first headline
+	title: Option<String>,
+    },
+    /// The query matched a line inside the note
+    Line{
+	path: PathBuf,
+	/// zero based line number
+	line: usize,
+	/// byte offset of the match in the note
+	offset: usize,
+	/// the part of the line around the match
+	context: String,
+    },
+}
+
+/// What was typed in the search bar: the text to look for, and `tag:name`
+/// and `prop:` filters the notes have to pass
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Query {
+    pub text: String,
+    /// lowercase prefixes of tags, a note needs a tag starting with each
+    pub tags: Vec<String>,
+    pub properties: Vec<PropertyFilter>,
+}
+
+/// `prop:key=value`, `prop:key<value` or `prop:key>value`: the note's
+/// property `key` has to contain the value, or sort before or after it.
+/// Times are written so they sort by time, `prop:created>2022-06` finds the
+/// notes made since June.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PropertyFilter {
+    /// lowercase
+    pub key: String,
+    pub comparison: Comparison,
+    /// lowercase
+    pub value: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Comparison {
+    Contains,
+    Before,
+    After,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Self {
+	let mut parsed = Self::default();
+	let mut words = vec![];
+	for word in query.split_whitespace() {
+	    if let Some(tag) = word.strip_prefix("tag:") {
+		let tag = tag.trim_start_matches('#').to_lowercase();
+		if !tag.is_empty() {
+		    parsed.tags.push(tag);
+		}
+	    } else if let Some(filter) = word.strip_prefix("prop:").and_then(PropertyFilter::parse) {
+		parsed.properties.push(filter);
+	    } else {
+		words.push(word);
+	    }
+	}
+	Self{text: words.join(" "), ..parsed}
+    }
+
+    /// Whether `note` passes the filters
+    pub fn admits(&self, note: &IndexedNote) -> bool {
+	self.tags.iter().all(|wanted| note.tags.iter().any(|tag| tag.starts_with(wanted.as_str()))) &&
+	    self.properties.iter().all(|filter| filter.admits(note))
+    }
+}
+
+impl PropertyFilter {
+    fn parse(word: &str) -> Option<Self> {
+	let at = word.find(['=', '<', '>'])?;
+	let key = &word[..at];
+	if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+	    return None;
+	}
+	let comparison = match &word[at..at + 1] {
+	    "=" => Comparison::Contains,
+	    "<" => Comparison::Before,
+	    _ => Comparison::After,
+	};
+	Some(Self{key: key.to_lowercase(), comparison, value: word[at + 1..].to_lowercase()})
+    }
+
+    fn admits(&self, note: &IndexedNote) -> bool {
+	let value = match note.properties.get(&self.key) {
+	    Some(value) => value.to_lowercase(),
+	    None => return false,
+	};
+	match self.comparison {
+	    Comparison::Contains => value.contains(&self.value),
+	    Comparison::Before => value < self.value,
+	    Comparison::After => value > self.value,
+	}
+    }
+}
+
+impl SearchHit {
+    pub fn path(&self) -> &Path {
+	match self {
+	    SearchHit::Name{path, ..} |
+	    SearchHit::Line{path, ..} => path,
+	}
+    }
+
+    /// Where in the note the editor should jump to
+    pub fn offset(&self) -> usize {
+	match self {
+	    SearchHit::Name{..} => 0,
+	    SearchHit::Line{offset, ..} => *offset,
+	}
+    }
+}
+
+/// Notes are written as `.snot`, and as `.snot.txt` where other apps only
+/// open text files
+pub(crate) fn is_note(path: &Path) -> bool {
+    path.is_file() && has_note_name(path)
+}
+
+/// Like [`is_note`] but for paths that might not exist anymore
+pub(crate) fn has_note_name(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    name.ends_with(".snot") || name.ends_with(".snot.txt")
+}
+
+/// The note's file name without the extension, which links to it use
+pub(crate) fn note_name(path: &Path) -> String {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    name.strip_suffix(".snot.txt")
+	.or_else(|| name.strip_suffix(".snot"))
+	.unwrap_or(&name)
+	.to_string()
+}
+
+/// Every note under `dir` and its subdirectories, hidden ones are skipped
+pub(crate) fn note_files(dir: &Path) -> Vec<PathBuf> {
+    dir.read_dir()
+	.map(|d| {
+	    d.filter_map(std::result::Result::ok)
+		.map(|f| f.path())
+		.flat_map(|f| match f.is_dir() {
+		    true if !is_hidden(&f) => note_files(&f),
+		    true => vec![],
+		    false => is_note(&f).then_some(f).into_iter().collect(),
+		})
+		.collect()
+	})
+	.unwrap_or_default()
+}
+
+/// Every note that passes the query's filters, followed by the lines
+/// containing its text in the notes the index has every word of it in, most
+/// recently changed notes first. Only the lines are matched against the
+/// text, the notes are left for the popup to fuzzy match.
+pub(crate) fn search_notes(index: &NoteIndex, query: &Query) -> Vec<SearchHit> {
+    let admitted = |path: &Path| index.get(path).is_some_and(|note| query.admits(note));
+    let names = index.notes()
+	.filter(|(_, note)| query.admits(note))
+	.map(|(path, note)| SearchHit::Name{
+	    path: path.to_path_buf(),
+	    title: note.headlines.first().cloned(),
+	});
+    let lines = index.candidates(&query.text)
+	.into_iter()
+	.filter(|f| admitted(f))
+	.flat_map(|f| std::fs::read_to_string(f)
+		  .map(|content| search_content(f, &content, &query.text))
+		  .unwrap_or_default());
+    let mut hits: Vec<_> = names.chain(lines).collect();
+    hits.sort_by_key(|hit| Reverse(index.get(hit.path()).map(|note| note.modified)));
+    hits
+}
+
+/// The lines of `content` that contain `query`, ignoring case
+pub(crate) fn search_content(path: &Path, content: &str, query: &str) -> Vec<SearchHit> {
+    let mut line_start = 0;
+    content.split_inclusive('\n')
+	.enumerate()
+	.filter_map(|(line, text)| {
+	    let start = line_start;
+	    line_start += text.len();
+	    let text = text.trim_end_matches(['\n', '\r']);
+	    find_ignore_case(text, query).map(|found| SearchHit::Line{
+		path: path.to_path_buf(),
+		line,
+		offset: start + found.start,
+		context: context(text, found),
+	    })
+	})
+	.collect()
+}
+
+/// The byte range of the first occurrence of `needle` in `haystack`
+pub(crate) fn find_ignore_case(haystack: &str, needle: &str) -> Option<Range<usize>> {
+    if needle.is_empty() {
+	return None;
+    }
+    let lower = |s: &str| s.chars().flat_map(char::to_lowercase).collect::<Vec<_>>();
+    let needle = lower(needle);
+    haystack.char_indices().find_map(|(start, _)| {
+	let mut rest = haystack[start..].char_indices();
+	let mut lowered = vec![];
+	let mut end = start;
+	while lowered.len() < needle.len() {
+	    let (i, c) = rest.next()?;
+	    lowered.extend(c.to_lowercase());
+	    end = start + i + c.len_utf8();
+	}
+	(lowered == needle).then_some(start..end)
+    })
+}
+
+fn context(line: &str, found: Range<usize>) -> String {
+    let (before, after) = (&line[..found.start], &line[found.end..]);
+    let skipped = before.chars().count().saturating_sub(CONTEXT_CHARS);
+    let cut_after = after.chars().count() > CONTEXT_CHARS;
+    format!(
+	"{}{}{}{}{}",
+	if skipped > 0 { "…" } else { "" },
+	before.chars().skip(skipped).collect::<String>().trim_start(),
+	&line[found],
+	after.chars().take(CONTEXT_CHARS).collect::<String>().trim_end(),
+	if cut_after { "…" } else { "" },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use std::time::SystemTime;
+
+    use crate::index::IndexedNote;
+
+    use super::{Comparison, PropertyFilter, Query, SearchHit, find_ignore_case, search_content};
+
+    #[test]
+    fn find_ignoring_case() {
+	assert_eq!(Some(4..9), find_ignore_case("foo HeLLo bar", "hello"));
+	assert_eq!(Some(2..6), find_ignore_case("a ÄbC d", "äBc"));
+	assert_eq!(None, find_ignore_case("foo", "foobar"));
+	assert_eq!(None, find_ignore_case("foo", ""));
+    }
+
+    #[test]
+    fn tag_filters_are_taken_out_of_the_query() {
+	let query = Query::parse("meeting tag:Work  notes tag:#q3 tag:");
+	assert_eq!("meeting notes", query.text);
+	assert_eq!(vec!["work", "q3"], query.tags);
+    }
+
+    #[test]
+    fn property_filters() {
+	let query = Query::parse("prop:a=b prop:created>2022-06 prop:x<=y prop:=z x=1 i<3 a->b");
+	assert_eq!("prop:=z x=1 i<3 a->b", query.text);
+	assert_eq!(3, query.properties.len());
+	assert_eq!(
+	    PropertyFilter{key: "created".into(), comparison: Comparison::After, value: "2022-06".into()},
+	    query.properties[1]
+	);
+	let note = |content| IndexedNote::new(content, SystemTime::UNIX_EPOCH);
+	let june = note(":PROPERTIES:\n:created: 2022-06-03\n:Source: The Book\n:END:");
+	let may = note(":PROPERTIES:\n:created: 2022-05-30\n:END:");
+	let admits = |query, note| Query::parse(query).admits(note);
+	assert!(admits("prop:created>2022-06 prop:source=book", &june));
+	assert!(!admits("prop:created>2022-06", &may));
+	assert!(admits("prop:created<2022-06", &may));
+	assert!(!admits("prop:source=", &may));
+	assert!(admits("source=", &may));
+    }
+
+    #[test]
+    fn search_lines_of_a_note() {
+	let content = "* groceries\nmilk\r\nEggs and more eggs\n";
+	let hits = search_content(Path::new("a.snot"), content, "eggs");
+	assert_eq!(
+	    vec![SearchHit::Line{
+		path: "a.snot".into(),
+		line: 2,
+		offset: 18,
+		context: "Eggs and more eggs".to_string(),
+	    }],
+	    hits
+	);
+    }
+
+    #[test]
+    fn long_lines_are_cut_around_the_match() {
+	let line = format!("{}needle{}", "x".repeat(100), "y".repeat(100));
+	let hits = search_content(Path::new("a.snot"), &line, "needle");
+	match &hits[0] {
+	    SearchHit::Line{context, offset, ..} => {
+		assert_eq!(100, *offset);
+		assert_eq!(format!("…{}needle{}…", "x".repeat(30), "y".repeat(30)), *context);
+	    },
+	    hit => panic!("unexpected {:?}", hit),
+	}
+    }
+}
@@ -0,0 +1,22 @@
+use config::{Config, ConfigError};
+
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// The settings from `~/.config/snott/config`, overridden by `SNOTT_*`
+/// environment variables. Without a config file the defaults are used.
+pub fn build_config() -> Result<Config, ConfigError>{
+    let home_dir = dirs::home_dir().unwrap_or_default();
+    let config_file = home_dir
+	.join(".config")
+	.join("snott")
+	.join("config");
+    Config::builder()
+        .add_source(config::File::from(config_file).required(false))
+        .add_source(config::Environment::with_prefix("SNOTT"))
+        .set_default("sync_dir", home_dir.display().to_string())?
+        .set_default("timestamp_format", DEFAULT_TIMESTAMP_FORMAT)?
+        .set_default("body_font_size", "20")?
+        .set_default("headline_font_size", "40")?
+        .set_default("persist_undo", true)?
+        .build()
+}
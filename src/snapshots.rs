@@ -0,0 +1,377 @@
+use std::cmp::Reverse;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+use eframe::egui;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+
+use crate::diff::{DiffLine, diff_lines};
+use crate::stable_hash::stable_hash;
+
+/// How long after a snapshot of a note the next one is taken
+pub(crate) const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// How many snapshots of a note are kept, older ones are deleted
+const MAX_SNAPSHOTS: usize = 100;
+
+/// A compressed revision of a note, kept in `.snott/history/`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Snapshot {
+    pub path: PathBuf,
+    pub taken: SystemTime,
+}
+
+/// A window listing the snapshots of a note, the selected one is compared
+/// to the note's content
+#[derive(Debug)]
+pub(crate) struct HistoryView {
+    pub note: PathBuf,
+    snapshots: Vec<Snapshot>,
+    selected: usize,
+    /// the content of the selected snapshot, or why it couldn't be read
+    shown: Option<Result<String, String>>,
+    /// the lines of the selected snapshot's diff against the note, with
+    /// the hash of the note's content they were found for
+    diff: Option<(u64, Vec<String>)>,
+}
+
+impl Snapshot {
+    pub fn read(&self) -> io::Result<String> {
+	let mut content = String::new();
+	GzDecoder::new(std::fs::File::open(&self.path)?).read_to_string(&mut content)?;
+	Ok(content)
+    }
+
+    fn label(&self) -> String {
+	DateTime::<Local>::from(self.taken).format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+}
+
+/// `.snott/<kind>/<the note's path in snots_dir>`, what's kept of a note
+/// is found by the same path
+fn kept_for(snots_dir: &Path, kind: &str, note: &Path) -> PathBuf {
+    let relative = note.strip_prefix(snots_dir)
+	.ok()
+	.or_else(|| note.file_name().map(Path::new))
+	.unwrap_or(note);
+    snots_dir.join(".snott").join(kind).join(relative)
+}
+
+/// `.snott/history/<the note's path in snots_dir>/`, every snapshot is named
+/// by when it was taken, `<millis>.gz` or `<millis>-<n>.gz` when more were
+/// taken in the same millisecond
+pub(crate) fn snapshot_dir(snots_dir: &Path, note: &Path) -> PathBuf {
+    kept_for(snots_dir, "history", note)
+}
+
+/// `.snott/undo/<the note's path in snots_dir>.json`, where the note's undo
+/// history is saved
+pub(crate) fn undo_file(snots_dir: &Path, note: &Path) -> PathBuf {
+    let mut file = kept_for(snots_dir, "undo", note).into_os_string();
+    file.push(".json");
+    file.into()
+}
+
+/// The snapshots of `note`, newest first
+pub(crate) fn snapshots(snots_dir: &Path, note: &Path) -> io::Result<Vec<Snapshot>> {
+    let entries = match std::fs::read_dir(snapshot_dir(snots_dir, note)) {
+	Ok(entries) => entries,
+	Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+	Err(e) => return Err(e),
+    };
+    let mut snapshots = vec![];
+    for entry in entries {
+	let path = entry?.path();
+	if let Some((millis, n)) = path.file_name().and_then(|name| taken_at(name.to_str()?)) {
+	    snapshots.push(((millis, n), Snapshot{path, taken: UNIX_EPOCH + Duration::from_millis(millis)}));
+	}
+    }
+    snapshots.sort_by_key(|(taken, _)| Reverse(*taken));
+    Ok(snapshots.into_iter().map(|(_, snapshot)| snapshot).collect())
+}
+
+/// The millisecond a snapshot was taken and how many were taken before
+/// it in that one
+fn taken_at(name: &str) -> Option<(u64, u32)> {
+    let name = name.strip_suffix(".gz")?;
+    match name.split_once('-') {
+	Some((millis, n)) => Some((millis.parse().ok()?, n.parse().ok()?)),
+	None => Some((name.parse().ok()?, 0)),
+    }
+}
+
+/// Takes a snapshot of `note` unless the last one is younger than `every`
+/// or has the same content, returns whether it did
+pub(crate) fn take_snapshot(snots_dir: &Path, note: &Path, content: &str, every: Duration) -> io::Result<bool> {
+    if let Some(latest) = snapshots(snots_dir, note)?.first() {
+	let recent = latest.taken.elapsed().is_ok_and(|age| age < every);
+	if recent || latest.read()? == content {
+	    return Ok(false);
+	}
+    }
+    let dir = snapshot_dir(snots_dir, note);
+    std::fs::create_dir_all(&dir)?;
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    write_snapshot(&dir, millis, content)?;
+    prune_snapshots(snots_dir, note, MAX_SNAPSHOTS)?;
+    Ok(true)
+}
+
+fn write_snapshot(dir: &Path, millis: u128, content: &str) -> io::Result<()> {
+    let mut n = 0;
+    let file = loop {
+	let name = match n {
+	    0 => format!("{}.gz", millis),
+	    n => format!("{}-{}.gz", millis, n),
+	};
+	match std::fs::OpenOptions::new().write(true).create_new(true).open(dir.join(name)) {
+	    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+	    file => break file?,
+	}
+    };
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(content.as_bytes())?;
+    encoder.finish()?.sync_all()
+}
+
+/// Deletes the oldest snapshots of `note` beyond the newest `keep`
+fn prune_snapshots(snots_dir: &Path, note: &Path, keep: usize) -> io::Result<()> {
+    for snapshot in snapshots(snots_dir, note)?.iter().skip(keep) {
+	std::fs::remove_file(&snapshot.path)?;
+    }
+    Ok(())
+}
+
+/// Takes a snapshot of what a note's file had before it's first
+/// overwritten, so the edits made since the note was opened can be undone
+pub(crate) fn snapshot_original(snots_dir: &Path, note: &Path, original: &str) -> io::Result<bool> {
+    match original.is_empty() {
+	true => Ok(false),
+	false => take_snapshot(snots_dir, note, original, Duration::ZERO),
+    }
+}
+
+/// Keeps the snapshots and the undo history of a note that was moved from
+/// `from` to `to`
+pub(crate) fn move_history(snots_dir: &Path, from: &Path, to: &Path) -> io::Result<()> {
+    let kept = [
+	(snapshot_dir(snots_dir, from), snapshot_dir(snots_dir, to)),
+	(undo_file(snots_dir, from), undo_file(snots_dir, to)),
+    ];
+    for (from, to) in kept.into_iter().filter(|(from, _)| from.exists()) {
+	if let Some(parent) = to.parent() {
+	    std::fs::create_dir_all(parent)?;
+	}
+	std::fs::rename(from, to)?;
+    }
+    Ok(())
+}
+
+impl HistoryView {
+    pub fn open(snots_dir: &Path, note: &Path) -> io::Result<Self> {
+	let mut view = Self{
+	    note: note.to_path_buf(),
+	    snapshots: snapshots(snots_dir, note)?,
+	    selected: 0,
+	    shown: None,
+	    diff: None,
+	};
+	view.select(0);
+	Ok(view)
+    }
+
+    fn select(&mut self, index: usize) {
+	self.selected = index;
+	self.shown = self.snapshots.get(index)
+	    .map(|snapshot| snapshot.read().map_err(|e| e.to_string()));
+	self.diff = None;
+    }
+
+    /// Compares the selected snapshot to `current`, unless it already was
+    /// to the same content, since a diff takes a while for long notes
+    fn update_diff(&mut self, current: &str) {
+	let hash = stable_hash(current);
+	if self.diff.as_ref().is_some_and(|(of, _)| *of == hash) {
+	    return;
+	}
+	self.diff = match &self.shown {
+	    Some(Ok(snapshot)) => Some((hash, diff_lines(snapshot, current).into_iter()
+					.map(|line| match line {
+					    DiffLine::Same(line) => format!("  {}", line),
+					    DiffLine::Removed(line) => format!("- {}", line),
+					    DiffLine::Added(line) => format!("+ {}", line),
+					})
+					.collect())),
+	    _ => None,
+	};
+    }
+
+    /// Shows the window until it's closed, which sets `open` to false.
+    /// Returns the content to restore the note to once it was asked for.
+    pub fn show(&mut self, ctx: &egui::Context, current: &str, open: &mut bool) -> Option<String> {
+	let name = self.note.file_name().unwrap_or_default().to_string_lossy().to_string();
+	let mut restored = None;
+	self.update_diff(current);
+	egui::Window::new(format!("History of {}", name))
+	    .open(open)
+	    .default_size([600.0, 400.0])
+	    .show(ctx, |ui| {
+		if self.snapshots.is_empty() {
+		    ui.label("no snapshots yet, one is taken when the note is saved");
+		    return;
+		}
+		ui.horizontal_top(|ui| {
+		    self.snapshot_list(ui);
+		    ui.separator();
+		    ui.vertical(|ui| match &self.shown {
+			Some(Ok(snapshot)) => {
+			    if ui.button("Restore").on_hover_text("replace the note with this snapshot").clicked() {
+				restored = Some(snapshot.clone());
+			    }
+			    let diff = self.diff.as_ref().map_or(&[][..], |(_, lines)| lines);
+			    egui::ScrollArea::both()
+				.id_source("snapshot_diff")
+				.show(ui, |ui| diff_view(ui, diff));
+			},
+			Some(Err(e)) => { ui.colored_label(egui::Color32::RED, e); },
+			None => (),
+		    });
+		});
+	    });
+	restored
+    }
+
+    fn snapshot_list(&mut self, ui: &mut egui::Ui) {
+	let mut selected = None;
+	egui::ScrollArea::vertical()
+	    .id_source("snapshot_list")
+	    .show(ui, |ui| {
+		for (i, snapshot) in self.snapshots.iter().enumerate() {
+		    if ui.selectable_label(i == self.selected, snapshot.label()).clicked() {
+			selected = Some(i);
+		    }
+		}
+	    });
+	if let Some(selected) = selected {
+	    self.select(selected);
+	}
+    }
+}
+
+/// The lines of a diff, removed ones in red and added ones in green
+fn diff_view(ui: &mut egui::Ui, diff: &[String]) {
+    if diff.iter().all(|line| line.starts_with(' ')) {
+	ui.label("same as the note");
+    }
+    for line in diff {
+	let text = egui::RichText::new(line);
+	let text = match line.chars().next() {
+	    Some('-') => text.color(egui::Color32::RED),
+	    Some('+') => text.color(egui::Color32::GREEN),
+	    _ => text.weak(),
+	};
+	ui.label(text.monospace());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::saving::SaveState;
+    use crate::test_util::ScratchDir;
+
+    use crate::snote::{Change, History};
+
+    use super::{HistoryView, move_history, prune_snapshots, snapshot_dir, snapshot_original, snapshots, take_snapshot, undo_file, write_snapshot};
+
+    #[test]
+    fn snapshots_are_taken_at_intervals() {
+	let dir = ScratchDir::new("snapshots");
+	let note = dir.join("sub").join("note.snot");
+	assert!(take_snapshot(&dir, &note, "* a", Duration::from_secs(60)).unwrap());
+	assert!(!take_snapshot(&dir, &note, "* b", Duration::from_secs(60)).unwrap());
+	std::thread::sleep(Duration::from_millis(5));
+	assert!(!take_snapshot(&dir, &note, "* a", Duration::ZERO).unwrap());
+	assert!(take_snapshot(&dir, &note, "* b", Duration::ZERO).unwrap());
+	let taken: Vec<_> = snapshots(&dir, &note).unwrap()
+	    .iter()
+	    .map(|snapshot| snapshot.read().unwrap())
+	    .collect();
+	assert_eq!(vec!["* b", "* a"], taken);
+    }
+
+    #[test]
+    fn snapshots_in_the_same_millisecond_are_all_kept() {
+	let dir = ScratchDir::new("snapshots-same-time");
+	let note = dir.join("note.snot");
+	std::fs::create_dir_all(snapshot_dir(&dir, &note)).unwrap();
+	for content in ["* a", "* b", "* c"] {
+	    write_snapshot(&snapshot_dir(&dir, &note), 1000, content).unwrap();
+	}
+	let taken: Vec<_> = snapshots(&dir, &note).unwrap()
+	    .iter()
+	    .map(|snapshot| snapshot.read().unwrap())
+	    .collect();
+	assert_eq!(vec!["* c", "* b", "* a"], taken);
+	prune_snapshots(&dir, &note, 2).unwrap();
+	let kept: Vec<_> = snapshots(&dir, &note).unwrap()
+	    .iter()
+	    .map(|snapshot| snapshot.read().unwrap())
+	    .collect();
+	assert_eq!(vec!["* c", "* b"], kept);
+    }
+
+    #[test]
+    fn diffs_are_kept_until_the_note_or_the_selection_changes() {
+	let dir = ScratchDir::new("snapshots-diff");
+	let note = dir.join("note.snot");
+	take_snapshot(&dir, &note, "a\nb", Duration::ZERO).unwrap();
+	let mut view = HistoryView::open(&dir, &note).unwrap();
+	view.update_diff("a\nc");
+	let diff = |view: &HistoryView| view.diff.as_ref().map(|(_, lines)| lines.clone());
+	assert_eq!(Some(vec!["  a".to_string(), "- b".to_string(), "+ c".to_string()]), diff(&view));
+	// the same content doesn't diff again, even if the snapshot was replaced
+	view.shown = Some(Ok("c".to_string()));
+	view.update_diff("a\nc");
+	assert_eq!(3, diff(&view).unwrap().len());
+	view.update_diff("c");
+	assert_eq!(Some(vec!["  c".to_string()]), diff(&view));
+	view.select(0);
+	assert_eq!(None, diff(&view));
+    }
+
+    #[test]
+    fn the_original_is_restorable_after_one_save() {
+	let dir = ScratchDir::new("snapshots-original");
+	let note = dir.join("note.snot");
+	std::fs::write(&note, "* original").unwrap();
+	let (_, mut saved) = SaveState::load(&note).unwrap();
+	assert!(snapshot_original(&dir, &note, saved.base()).unwrap());
+	saved.save(&note, "").unwrap();
+	assert!(!snapshot_original(&dir, &note, saved.base()).unwrap());
+	let restorable: Vec<_> = snapshots(&dir, &note).unwrap()
+	    .iter()
+	    .map(|snapshot| snapshot.read().unwrap())
+	    .collect();
+	assert_eq!(vec!["* original"], restorable);
+    }
+
+    #[test]
+    fn moved_notes_keep_their_history() {
+	let dir = ScratchDir::new("snapshots-move");
+	let (from, to) = (dir.join("note.snot"), dir.join("sub").join("note.snot"));
+	assert!(snapshots(&dir, &from).unwrap().is_empty());
+	take_snapshot(&dir, &from, "* a", Duration::ZERO).unwrap();
+	let mut history = History::default();
+	history.record(Change::new(2, "", "a"));
+	history.save(&undo_file(&dir, &from), "* a").unwrap();
+	move_history(&dir, &from, &to).unwrap();
+	assert!(snapshots(&dir, &from).unwrap().is_empty());
+	assert_eq!(1, snapshots(&dir, &to).unwrap().len());
+	assert_eq!(History::default(), History::load(&undo_file(&dir, &from), "* a"));
+	assert_ne!(History::default(), History::load(&undo_file(&dir, &to), "* a"));
+	assert_eq!(dir.join(".snott").join("undo").join("sub").join("note.snot.json"), undo_file(&dir, &to));
+    }
+}
@@ -0,0 +1,186 @@
+use std::ops::Range;
+
+/// What a piece of code is, to color it by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    Plain,
+    Keyword,
+    String,
+    Number,
+    Comment,
+}
+
+/// Just enough of a language to color its code
+struct Syntax {
+    keywords: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// in shells `#` only starts a comment at the start of a word
+    comment_starts_word: bool,
+}
+
+const SHELL: Syntax = Syntax{
+    keywords: &["if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done",
+		"case", "esac", "in", "function", "return", "local", "export"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    comment_starts_word: true,
+};
+
+const PYTHON: Syntax = Syntax{
+    keywords: &["and", "as", "assert", "async", "await", "break", "class", "continue", "def",
+		"del", "elif", "else", "except", "False", "finally", "for", "from", "global",
+		"if", "import", "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass",
+		"raise", "return", "True", "try", "while", "with", "yield"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    comment_starts_word: false,
+};
+
+const RUST: Syntax = Syntax{
+    keywords: &["as", "async", "await", "break", "const", "continue", "crate", "dyn", "else",
+		"enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop",
+		"match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static",
+		"struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+    comment_starts_word: false,
+};
+
+const JAVASCRIPT: Syntax = Syntax{
+    keywords: &["async", "await", "break", "case", "catch", "class", "const", "continue",
+		"default", "delete", "do", "else", "export", "extends", "false", "finally",
+		"for", "function", "if", "import", "in", "instanceof", "let", "new", "null",
+		"of", "return", "super", "switch", "this", "throw", "true", "try", "typeof",
+		"undefined", "var", "void", "while", "yield"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    comment_starts_word: false,
+};
+
+const C: Syntax = Syntax{
+    keywords: &["auto", "break", "case", "char", "class", "const", "continue", "default",
+		"delete", "do", "double", "else", "enum", "extern", "false", "float", "for",
+		"goto", "if", "int", "long", "namespace", "new", "nullptr", "private",
+		"protected", "public", "return", "short", "signed", "sizeof", "static",
+		"struct", "switch", "template", "true", "typedef", "union", "unsigned", "void",
+		"volatile", "while"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    comment_starts_word: false,
+};
+
+impl Syntax {
+    fn of(language: &str) -> Option<&'static Syntax> {
+	match language.to_lowercase().as_str() {
+	    "sh" | "bash" | "shell" | "zsh" | "console" => Some(&SHELL),
+	    "python" | "py" => Some(&PYTHON),
+	    "rust" | "rs" => Some(&RUST),
+	    "js" | "javascript" | "ts" | "typescript" => Some(&JAVASCRIPT),
+	    "c" | "h" | "cpp" | "c++" | "java" => Some(&C),
+	    _ => None,
+	}
+    }
+
+    /// The length of the comment starting at the start of `code`
+    fn comment(&self, code: &str, after_word: bool) -> Option<usize> {
+	if let Some((open, close)) = self.block_comment.filter(|(open, _)| code.starts_with(open)) {
+	    return Some(code[open.len()..].find(close).map_or(code.len(), |end| open.len() + end + close.len()));
+	}
+	let line_comment = self.line_comments.iter().any(|start| code.starts_with(start));
+	(line_comment && !(self.comment_starts_word && after_word))
+	    .then(|| code.find(['\n', '\r']).unwrap_or(code.len()))
+    }
+
+    /// The length of the string starting at the start of `code`, up to the
+    /// matching quote or the end of the code
+    fn string(&self, code: &str) -> Option<usize> {
+	let quote = code.chars().next().filter(|c| self.quotes.contains(c))?;
+	let mut escaped = false;
+	for (i, c) in code.char_indices().skip(1) {
+	    match c {
+		_ if escaped => escaped = false,
+		'\\' => escaped = true,
+		_ if c == quote => return Some(i + 1),
+		_ => (),
+	    }
+	}
+	Some(code.len())
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Splits `code` in `language` into ranges to color, unknown languages
+/// come out as plain text
+pub(crate) fn highlight(language: &str, code: &str) -> Vec<(Range<usize>, TokenKind)> {
+    let syntax = match Syntax::of(language) {
+	Some(syntax) => syntax,
+	None => return vec![(0..code.len(), TokenKind::Plain)],
+    };
+    let mut tokens: Vec<(Range<usize>, TokenKind)> = vec![];
+    let mut push = |range: Range<usize>, kind| match tokens.last_mut() {
+	Some((last, TokenKind::Plain)) if kind == TokenKind::Plain => last.end = range.end,
+	_ => tokens.push((range, kind)),
+    };
+    let mut at = 0;
+    while let Some(c) = code[at..].chars().next() {
+	let rest = &code[at..];
+	let after_word = code[..at].chars().next_back().is_some_and(|c| !c.is_whitespace());
+	let (len, kind) = if let Some(len) = syntax.comment(rest, after_word) {
+	    (len, TokenKind::Comment)
+	} else if let Some(len) = syntax.string(rest) {
+	    (len, TokenKind::String)
+	} else if is_word_char(c) {
+	    let len = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+	    let word = &rest[..len];
+	    let kind = if c.is_ascii_digit() {
+		TokenKind::Number
+	    } else if syntax.keywords.contains(&word) {
+		TokenKind::Keyword
+	    } else {
+		TokenKind::Plain
+	    };
+	    (len, kind)
+	} else {
+	    (c.len_utf8(), TokenKind::Plain)
+	};
+	push(at..at + len, kind);
+	at += len;
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TokenKind, highlight};
+
+    fn tokens<'c>(language: &str, code: &'c str) -> Vec<(&'c str, TokenKind)> {
+	highlight(language, code).into_iter()
+	    .map(|(range, kind)| (&code[range], kind))
+	    .collect()
+    }
+
+    #[test]
+    fn code_is_split_by_language() {
+	use TokenKind::*;
+	assert_eq!(
+	    vec![("for", Keyword), (" f ", Plain), ("in", Keyword), (" ", Plain), ("\"$@\"", String),
+		 ("; ", Plain), ("do", Keyword), (" echo ${#f} ", Plain), ("# count", Comment)],
+	    tokens("sh", "for f in \"$@\"; do echo ${#f} # count")
+	);
+	assert_eq!(
+	    vec![("let", Keyword), (" x = ", Plain), ("42", Number), ("; ", Plain), ("/* \"no\" */", Comment)],
+	    tokens("Rust", "let x = 42; /* \"no\" */")
+	);
+	assert_eq!(vec![("let x = 1", Plain)], tokens("unknown", "let x = 1"));
+    }
+}
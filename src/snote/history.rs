@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::stable_hash::stable_hash;
+
+/// How many changes can be undone
+const MAX_UNDO: usize = 1000;
+/// Changes further apart than this are undone separately
+const GROUP_WITHIN: Duration = Duration::from_secs(1);
+
+/// A change to a note's text: `removed` was replaced by `inserted` at the
+/// byte offset `at`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub at: usize,
+    pub removed: String,
+    pub inserted: String,
+    /// when the change was last extended, changes read from disk are never
+    /// extended
+    #[serde(skip)]
+    made: Option<Instant>,
+}
+
+/// The changes made to a note, so they can be undone and redone
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+/// The saved history of a note, only valid for the content it ended at
+#[derive(Serialize, Deserialize)]
+struct SavedHistory {
+    content_hash: u64,
+    history: History,
+}
+
+impl Change {
+    pub fn new(at: usize, removed: &str, inserted: &str) -> Self {
+	Self{
+	    at,
+	    removed: removed.to_string(),
+	    inserted: inserted.to_string(),
+	    made: Some(Instant::now()),
+	}
+    }
+
+    /// The byte range the change replaced in the text before it
+    pub fn range(&self) -> Range<usize> {
+	self.at..self.at + self.removed.len()
+    }
+
+    /// The change that takes the text back to how it was before this one
+    pub fn inverse(&self) -> Self {
+	Self{at: self.at, removed: self.inserted.clone(), inserted: self.removed.clone(), made: None}
+    }
+
+    /// Extends this change with `next` when it continues it, like typing
+    /// or deleting one char after another, returns whether it did
+    fn extend(&mut self, next: &Change) -> bool {
+	let recent = self.made.is_some_and(|made| made.elapsed() < GROUP_WITHIN);
+	// every line is undone on its own
+	let has_newline = |c: &Change| c.inserted.contains('\n') || c.removed.contains('\n');
+	if !recent || has_newline(self) || has_newline(next) {
+	    return false;
+	}
+	let end = self.at + self.inserted.len();
+	let extended = match (next.removed.is_empty(), next.inserted.is_empty()) {
+	    // typing on
+	    (true, false) if next.at == end => {
+		self.inserted.push_str(&next.inserted);
+		true
+	    },
+	    // backspacing over what was just typed
+	    (false, true) if next.range().end == end && next.at >= self.at => {
+		self.inserted.truncate(next.at - self.at);
+		true
+	    },
+	    // backspacing further back
+	    (false, true) if next.range().end == self.at && self.inserted.is_empty() => {
+		self.removed.insert_str(0, &next.removed);
+		self.at = next.at;
+		true
+	    },
+	    // deleting forwards
+	    (false, true) if next.at == self.at && self.inserted.is_empty() => {
+		self.removed.push_str(&next.removed);
+		true
+	    },
+	    _ => false,
+	};
+	if extended {
+	    self.made = next.made;
+	}
+	extended
+    }
+}
+
+impl History {
+    /// Remembers a change that was just made, nothing can be redone after it
+    pub fn record(&mut self, change: Change) {
+	self.redo.clear();
+	if !self.undo.last_mut().is_some_and(|last| last.extend(&change)) {
+	    self.undo.push(change);
+	}
+	if self.undo.len() > MAX_UNDO {
+	    self.undo.remove(0);
+	}
+    }
+
+    /// The change that undoes the last one
+    pub fn undo(&mut self) -> Option<Change> {
+	let change = self.undo.pop()?;
+	let inverse = change.inverse();
+	self.redo.push(change);
+	Some(inverse)
+    }
+
+    /// The last undone change
+    pub fn redo(&mut self) -> Option<Change> {
+	let mut change = self.redo.pop()?;
+	change.made = None;
+	self.undo.push(change.clone());
+	Some(change)
+    }
+
+    /// Reads the history saved in `file` for a note whose text is `content`,
+    /// it's dropped when the note was changed by something that didn't save it
+    pub fn load(file: &Path, content: &str) -> Self {
+	std::fs::read_to_string(file)
+	    .ok()
+	    .and_then(|json| serde_json::from_str::<SavedHistory>(&json).ok())
+	    .filter(|saved| saved.content_hash == stable_hash(content))
+	    .map(|saved| saved.history)
+	    .unwrap_or_default()
+    }
+
+    /// Saves the history in `file` for a note whose text is `content`
+    pub fn save(&self, file: &Path, content: &str) -> Result<(), Box<dyn Error>> {
+	if let Some(dir) = file.parent() {
+	    std::fs::create_dir_all(dir)?;
+	}
+	let saved = SavedHistory{content_hash: stable_hash(content), history: self.clone()};
+	std::fs::write(file, serde_json::to_string(&saved)?)?;
+	Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Change, History};
+
+    fn history_of(changes: &[(usize, &str, &str)]) -> History {
+	let mut history = History::default();
+	for (at, removed, inserted) in changes {
+	    history.record(Change::new(*at, removed, inserted));
+	}
+	history
+    }
+
+    fn undo(history: &mut History) -> Option<(usize, String, String)> {
+	history.undo().map(|c| (c.at, c.removed, c.inserted))
+    }
+
+    #[test]
+    fn typing_is_undone_at_once() {
+	let mut history = history_of(&[(0, "", "a"), (1, "", "b"), (2, "", "c"), (2, "c", "")]);
+	assert_eq!(Some((0, "ab".to_string(), "".to_string())), undo(&mut history));
+	assert_eq!(None, undo(&mut history));
+    }
+
+    #[test]
+    fn deleting_is_undone_at_once() {
+	let mut history = history_of(&[(5, "e", ""), (4, "d", ""), (4, "f", "")]);
+	assert_eq!(Some((4, "".to_string(), "def".to_string())), undo(&mut history));
+    }
+
+    #[test]
+    fn lines_and_jumps_are_undone_separately() {
+	let mut history = history_of(&[(0, "", "a"), (1, "", "\n"), (2, "", "b"), (0, "", "c")]);
+	assert_eq!(Some((0, "c".to_string(), "".to_string())), undo(&mut history));
+	assert_eq!(Some((2, "b".to_string(), "".to_string())), undo(&mut history));
+	assert_eq!(Some((1, "\n".to_string(), "".to_string())), undo(&mut history));
+    }
+
+    #[test]
+    fn redo_after_undo() {
+	let mut history = history_of(&[(0, "", "a"), (0, "a", "b")]);
+	undo(&mut history);
+	assert_eq!(Some(Change::new(0, "a", "b").range()), history.redo().map(|c| c.range()));
+	assert_eq!(None, history.redo());
+	undo(&mut history);
+	history.record(Change::new(0, "", "x"));
+	assert_eq!(None, history.redo());
+    }
+}
@@ -1,8 +1,16 @@
 
 mod snote_parser;
 mod snote_hightlighter;
+#[allow(clippy::module_inception)]
 mod snote;
+mod history;
+mod code_highlighter;
+mod properties;
 
-pub use snote_parser::{snote, SNoteSection};
-pub use snote_hightlighter::snote_widget;
-pub use snote::SNote;
+pub use snote_parser::{snote, ListKind, ListMarker, Markup, Property, SNoteSection, TableRow, Task, TaskKind};
+pub use snote_hightlighter::{snote_widget, SNoteEditor};
+pub(crate) use snote_hightlighter::snote_layout_job;
+pub(crate) use snote_parser::parse_inlines;
+pub use snote::{LinkTarget, SNote};
+pub use history::{Change, History};
+pub use properties::{Properties, TIME_FORMAT, parse_time};
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+/// How times are written to properties, it sorts the same as the times
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The metadata of a note, from the `:PROPERTIES:` drawer at its top or
+/// right after its first headline
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Properties {
+    pub created: Option<NaiveDateTime>,
+    pub modified: Option<NaiveDateTime>,
+    pub tags: Vec<String>,
+    /// other names links to the note can use
+    pub aliases: Vec<String>,
+    pub source: Option<String>,
+    /// every other property by its lowercase key
+    pub other: BTreeMap<String, String>,
+}
+
+impl Properties {
+    /// Reads the `key: value` pairs of a drawer, keys are case insensitive
+    /// and a later value replaces an earlier one
+    pub fn from_pairs<'p>(pairs: impl IntoIterator<Item = (&'p str, &'p str)>) -> Self {
+	let mut properties = Self::default();
+	for (key, value) in pairs {
+	    let value = value.trim();
+	    match key.to_lowercase().as_str() {
+		"created" => properties.created = parse_time(value),
+		"modified" => properties.modified = parse_time(value),
+		"tags" => properties.tags = value
+		    .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+		    .map(|tag| tag.trim_start_matches('#'))
+		    .filter(|tag| !tag.is_empty())
+		    .map(str::to_string)
+		    .collect(),
+		"aliases" | "alias" => properties.aliases = value.split(',')
+		    .map(str::trim)
+		    .filter(|alias| !alias.is_empty())
+		    .map(str::to_string)
+		    .collect(),
+		"source" => properties.source = Some(value.to_string()).filter(|source| !source.is_empty()),
+		key => {
+		    properties.other.insert(key.to_string(), value.to_string());
+		},
+	    }
+	}
+	properties
+    }
+
+    /// Every property written as text, times in [`TIME_FORMAT`] and lists
+    /// separated by commas, for searching
+    pub fn to_map(&self) -> BTreeMap<String, String> {
+	let time = |time: &Option<NaiveDateTime>| time.map(|time| time.format(TIME_FORMAT).to_string());
+	let list = |list: &[String]| Some(list.join(", ")).filter(|joined| !joined.is_empty());
+	let typed = [
+	    ("created", time(&self.created)),
+	    ("modified", time(&self.modified)),
+	    ("tags", list(&self.tags)),
+	    ("aliases", list(&self.aliases)),
+	    ("source", self.source.clone()),
+	];
+	typed.into_iter()
+	    .filter_map(|(key, value)| Some((key.to_string(), value?)))
+	    .chain(self.other.clone())
+	    .collect()
+    }
+}
+
+/// Reads [`TIME_FORMAT`] and the usual ways of writing a time by hand, org
+/// timestamps like `[2022-05-01 Sun 10:00]` included
+pub fn parse_time(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim_matches(|c: char| c.is_whitespace() || "[]<>".contains(c));
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %a %H:%M"]
+	.iter()
+	.find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+	.or_else(|| ["%Y-%m-%d", "%Y-%m-%d %a"].iter()
+		 .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+		 .and_then(|date| date.and_hms_opt(0, 0, 0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{Properties, parse_time};
+
+    #[test]
+    fn properties_are_typed() {
+	let properties = Properties::from_pairs([
+	    ("Created", "[2022-05-01 Sun 10:30]"),
+	    ("tags", ":work:#q3, ideas"),
+	    ("aliases", "Project X, px"),
+	    ("source", " https://example.com "),
+	    ("Status", "draft"),
+	]);
+	assert_eq!(NaiveDate::from_ymd_opt(2022, 5, 1).and_then(|d| d.and_hms_opt(10, 30, 0)), properties.created);
+	assert_eq!(vec!["work", "q3", "ideas"], properties.tags);
+	assert_eq!(vec!["Project X", "px"], properties.aliases);
+	assert_eq!(Some("https://example.com"), properties.source.as_deref());
+	let map = properties.to_map();
+	assert_eq!(Some("2022-05-01 10:30:00"), map.get("created").map(String::as_str));
+	assert_eq!(Some("draft"), map.get("status").map(String::as_str));
+	assert_eq!(None, map.get("modified"));
+    }
+
+    #[test]
+    fn times_are_read_in_many_formats() {
+	let day = NaiveDate::from_ymd_opt(2022, 1, 2);
+	assert_eq!(day.and_then(|d| d.and_hms_opt(0, 0, 0)), parse_time("2022-01-02"));
+	assert_eq!(day.and_then(|d| d.and_hms_opt(3, 4, 5)), parse_time("2022-01-02T03:04:05"));
+	assert_eq!(None, parse_time("yesterday"));
+    }
+}
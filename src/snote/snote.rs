@@ -1,34 +1,80 @@
+use std::collections::BTreeSet;
 use std::convert::Infallible;
+use std::ops::Range;
 use std::str::FromStr;
 
-use chumsky::Parser;
-// use chumsky::error::Error;
+use chumsky::prelude::Simple;
+use chumsky::error::SimpleReason;
+use chumsky::Error;
 
-use super::{SNoteSection, snote};
+use super::{Change, History, ListKind, Properties, Property, SNoteSection, TableRow};
+use super::snote_parser::{nest, parse_blocks};
 
 
 
 
 #[derive(Debug)]
 pub struct SNote{
+    /// The note's text, change it through [`SNote::replace_range`] or
+    /// [`SNote::set_raw`] so the parsed sections stay in sync
     pub raw_content: String,
-    sections: Vec<SNoteSection>
+    pub(crate) parsed: ParsedNote,
+    pub history: History,
 }
 
+/// What a link in a note points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget{
+    /// the name or title of another note, from `[[name]]`
+    Note(String),
+    /// an `http(s)://` url or a `file:` path
+    Url(String),
+}
+
+/// A change to a note's text: the byte `range` of the text before the edit
+/// was replaced by `inserted` bytes
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub(crate) struct Edit{
+    pub range: Range<usize>,
+    pub inserted: usize,
+}
+
+/// The flat blocks of a note, kept up to date by reparsing only the blocks
+/// an edit touched instead of the whole text
+#[derive(Debug, Default)]
+pub(crate) struct ParsedNote{
+    blocks: Vec<SNoteSection>,
+    diagnostics: Vec<Simple<char>>,
+}
 
+
+// the impls below the tests are kept where they were
+#[allow(clippy::items_after_test_module)]
 #[cfg(test)]
 mod tests{
+    use std::ops::Range;
     use std::str::FromStr;
 
     use crate::snote::SNoteSection;
 
-    use super::SNote;
+    use super::{Edit, LinkTarget, SNote};
 
     #[test]
     fn failed_parsing_creates_a_single_paragraph() {
 	let content = "not a valid note because theres no headline\nlalala";
 	let note = SNote::from_str(content).unwrap();
-	assert_eq!(note.sections, vec![SNoteSection::Paragraph(0..content.len())])
+	assert_eq!(note.sections(), vec![SNoteSection::Paragraph(0..content.len(), vec![])])
+    }
+
+    #[test]
+    fn errors_become_diagnostics() {
+	let note = SNote::from_str("* headline\n- [] broken box\nmore text").unwrap();
+	assert_eq!(1, note.diagnostics().len());
+	assert_eq!(1, note.sections().len());
+	assert_eq!(2, note.sections()[0].children().len());
+
+	let note = note.set_raw("* headline\n- [ ] fixed box");
+	assert!(note.diagnostics().is_empty());
     }
 
     #[test]
@@ -50,12 +96,193 @@ mod tests{
 	assert_eq!(&note.raw_content, new_content);
 
     }
+
+    fn assert_same_as_full_parse(note: &SNote) {
+	let full = SNote::from_str(&note.raw_content).unwrap();
+	assert_eq!(full.parsed.blocks, note.parsed.blocks, "{:?}", note.raw_content);
+	assert_eq!(full.diagnostics(), note.diagnostics(), "{:?}", note.raw_content);
+    }
+
+    #[test]
+    fn incremental_edits_match_a_full_parse() {
+	let mut note = SNote::from_str("* a\n\nfirst\nparagraph\n\n- [ ] item\n\n** b\nlast").unwrap();
+	let edits: [(Range<usize>, &str); 9] = [
+	    (0..0, "intro\n\n"),
+	    (13..13, " *bold*"),
+	    (18..19, ""),
+	    (23..23, "\n* split\n"),
+	    (38..38, "- [?] bad\n"),
+	    (40..41, " "),
+	    (0..7, ""),
+	    (usize::MAX..usize::MAX, "\n\nmore"),
+	    (2..6, "DONE"),
+	];
+	for (range, text) in edits {
+	    let range = range.start.min(note.raw_content.len())..range.end.min(note.raw_content.len());
+	    note.replace_range(range, text);
+	    assert_same_as_full_parse(&note);
+	}
+    }
+
+    #[test]
+    fn typing_a_note_char_by_char() {
+	let text = "* TODO title\n\nsome *text*\n- [x] done\n\n** sub\n`code` here";
+	let mut note = SNote::new();
+	for (i, c) in text.char_indices() {
+	    note.replace_range(i..i, &c.to_string());
+	    assert_same_as_full_parse(&note);
+	}
+	for _ in 0..text.len() {
+	    note.replace_range(0..1, "");
+	    assert_same_as_full_parse(&note);
+	}
+    }
+
+    #[test]
+    fn unmaking_a_headline_joins_the_paragraph_above() {
+	let mut note = SNote::from_str("a\n* b").unwrap();
+	note.replace_range(3..4, "");
+	assert_same_as_full_parse(&note);
+    }
+
+    #[test]
+    fn typing_a_code_block() {
+	let text = "a\n```sh\necho\n\n* not a headline\n```\n\nafter";
+	let mut note = SNote::new();
+	for (i, c) in text.char_indices() {
+	    note.replace_range(i..i, &c.to_string());
+	    assert_same_as_full_parse(&note);
+	}
+	for at in (0..text.len()).rev() {
+	    note.replace_range(at..at + 1, "");
+	    assert_same_as_full_parse(&note);
+	}
+    }
+
+    #[test]
+    fn undo_and_redo() {
+	let mut note = SNote::from_str("* title").unwrap();
+	note.replace_range(2..7, "headline");
+	note.replace_range(0..0, "intro\n");
+	assert_eq!(Some(0), note.undo());
+	assert_eq!("* headline", note.raw_content);
+	assert_eq!(Some(7), note.undo());
+	assert_eq!("* title", note.raw_content);
+	assert_eq!(None, note.undo());
+	assert_eq!(Some(10), note.redo());
+	assert_eq!("* headline", note.raw_content);
+	assert_same_as_full_parse(&note);
+    }
+
+    #[test]
+    fn replacing_all_only_changes_the_difference() {
+	let mut note = SNote::from_str("* a\nsame\nb ü").unwrap();
+	note.replace_all("* a\nchanged\nb ü");
+	assert_eq!("* a\nchanged\nb ü", note.raw_content);
+	assert_eq!(Some(8), note.undo());
+	assert_eq!("* a\nsame\nb ü", note.raw_content);
+	note.replace_all("* a\nsame\nb ü");
+	assert_eq!(None, note.undo());
+	note.replace_all("");
+	assert_eq!("", note.raw_content);
+	assert_same_as_full_parse(&note);
+    }
+
+    #[test]
+    fn tags_of_a_note() {
+	let note = SNote::from_str("* a :work:home:\n#home and #later").unwrap();
+	let tags: Vec<_> = note.tags().into_iter().collect();
+	assert_eq!(vec!["home", "later", "work"], tags);
+    }
+
+    #[test]
+    fn links_under_the_cursor() {
+	let note = SNote::from_str("[[a note]] https://x.org").unwrap();
+	assert_eq!(Some(LinkTarget::Note("a note".to_string())), note.link_at(3));
+	assert_eq!(None, note.link_at(10));
+	assert_eq!(Some(LinkTarget::Url("https://x.org".to_string())), note.link_at(12));
+    }
+
+    #[test]
+    fn enter_continues_lists() {
+	let mut note = SNote::from_str("1. a\n   - [x] b\n2. c\n3. d\n\ntext").unwrap();
+	assert_eq!(None, note.continue_list(1));
+	assert_eq!(None, note.continue_list(28));
+	assert_eq!(Some(28), note.continue_list(24));
+	assert_eq!("1. a\n   - [x] b\n2. c\n3. \n4. d\n\ntext", note.raw_content);
+	assert_eq!(Some(21), note.continue_list(24));
+	assert_eq!("1. a\n   - [x] b\n2. c\n\n4. d\n\ntext", note.raw_content);
+	assert_eq!(Some(25), note.continue_list(15));
+	assert_eq!("1. a\n   - [x] b\n   - [ ] \n2. c\n\n4. d\n\ntext", note.raw_content);
+	assert_eq!(Some(8), note.continue_list(4));
+	assert_eq!("1. a\n2. \n   - [x] b\n   - [ ] \n3. c\n\n4. d\n\ntext", note.raw_content);
+	note.undo();
+	assert_eq!("1. a\n   - [x] b\n   - [ ] \n2. c\n\n4. d\n\ntext", note.raw_content);
+    }
+
+    #[test]
+    fn tab_formats_tables() {
+	let mut note = SNote::from_str("text\n|a|bb\n|-\n| ccc |\n\nafter").unwrap();
+	assert_eq!(None, note.format_table(2));
+	assert_eq!(Some(13), note.format_table(7));
+	assert_eq!("text\n| a   | bb |\n|-----+----|\n| ccc |    |\n\nafter", note.raw_content);
+	assert_eq!(Some(33), note.format_table(13));
+	assert_eq!(Some(39), note.format_table(33));
+	assert_eq!(Some(39), note.format_table(39));
+	assert_eq!("text\n| a   | bb |\n|-----+----|\n| ccc |    |\n\nafter", note.raw_content);
+    }
+
+    #[test]
+    fn setting_properties() {
+	let mut note = SNote::from_str("* title\nbody #b").unwrap();
+	note.set_property("created", "2022-01-01 10:00:00");
+	assert_eq!("* title\n:PROPERTIES:\n:created: 2022-01-01 10:00:00\n:END:\nbody #b", note.raw_content);
+	note.set_property("tags", "a");
+	note.set_property("CREATED", "2023-01-01");
+	assert_eq!("* title\n:PROPERTIES:\n:CREATED: 2023-01-01\n:tags: a\n:END:\nbody #b", note.raw_content);
+	assert_eq!(vec!["a", "b"], note.tags().into_iter().collect::<Vec<_>>());
+	assert!(note.properties().created.is_some());
+
+	let mut note = SNote::from_str("no headline").unwrap();
+	note.set_property("source", "x");
+	assert_eq!(":PROPERTIES:\n:source: x\n:END:\nno headline", note.raw_content);
+	assert_eq!(Some("x".to_string()), note.properties().source);
+    }
+
+    #[test]
+    fn completing_links() {
+	let mut note = SNote::from_str("* a\nsee [[pro and [[x]]").unwrap();
+	assert_eq!(Some(10..13), note.unfinished_link_at(13));
+	assert_eq!(Some(10..10), note.unfinished_link_at(10));
+	assert_eq!(None, note.unfinished_link_at(4));
+	assert_eq!(None, note.unfinished_link_at(note.raw_content.len()));
+	assert_eq!(21, note.complete_link(10..13, "Project X"));
+	assert_eq!("* a\nsee [[Project X]] and [[x]]", note.raw_content);
+	assert_eq!(33, note.complete_link(28..29, "xyz"));
+	assert_eq!("* a\nsee [[Project X]] and [[xyz]]", note.raw_content);
+    }
+
+    #[test]
+    fn merging_edits() {
+	let typed = Edit{range: 5..5, inserted: 1}
+	    .then(Edit{range: 6..6, inserted: 1});
+	assert_eq!(Edit{range: 5..5, inserted: 2}, typed);
+
+	let selection_replaced = Edit{range: 2..4, inserted: 0}
+	    .then(Edit{range: 2..2, inserted: 3});
+	assert_eq!(Edit{range: 2..4, inserted: 3}, selection_replaced);
+
+	let far_apart = Edit{range: 10..12, inserted: 5}
+	    .then(Edit{range: 1..2, inserted: 0});
+	assert_eq!(Edit{range: 1..12, inserted: 13}, far_apart);
+    }
 }
 impl SNote {
     pub(crate) fn new() -> Self{
 	Self {
 	    raw_content: Default::default(),
-	    sections: snote().parse("").unwrap_or_default()
+	    parsed: ParsedNote::new(""),
+	    history: Default::default(),
 	}
     }
 
@@ -64,22 +291,399 @@ impl SNote {
 	self.update_sections()
     }
 
+    /// Replaces a byte range of the note and reparses only what it touched
+    pub fn replace_range(&mut self, range: Range<usize>, replacement: &str) {
+	let change = Change::new(range.start, &self.raw_content[range], replacement);
+	self.apply(&change);
+	self.history.record(change);
+    }
+
+    /// Changes the note to `text`, as a single change that can be undone
+    pub fn replace_all(&mut self, text: &str) {
+	let old = self.raw_content.as_str();
+	let prefix = common_prefix(old.char_indices(), text.char_indices());
+	let (old_rest, text_rest) = (&old[prefix..], &text[prefix..]);
+	let suffix = common_prefix(
+	    old_rest.char_indices().rev().map(|(i, c)| (old_rest.len() - i - c.len_utf8(), c)),
+	    text_rest.char_indices().rev().map(|(i, c)| (text_rest.len() - i - c.len_utf8(), c)),
+	);
+	let replacement = text_rest[..text_rest.len() - suffix].to_string();
+	if prefix != old.len() || !replacement.is_empty() {
+	    self.replace_range(prefix..old.len() - suffix, &replacement);
+	}
+    }
+
+    /// Undoes the last change, returns where it was so the cursor can go there
+    pub fn undo(&mut self) -> Option<usize> {
+	let change = self.history.undo()?;
+	self.apply(&change);
+	Some(change.at + change.inserted.len())
+    }
+
+    /// Redoes the last undone change, returns where it was
+    pub fn redo(&mut self) -> Option<usize> {
+	let change = self.history.redo()?;
+	self.apply(&change);
+	Some(change.at + change.inserted.len())
+    }
+
+    fn apply(&mut self, change: &Change) {
+	let edit = Edit{range: change.range(), inserted: change.inserted.len()};
+	self.raw_content.replace_range(change.range(), &change.inserted);
+	self.parsed.edited(&self.raw_content, &edit);
+    }
+
+    /// The note as a tree of sections
+    pub fn sections(&self) -> Vec<SNoteSection> {
+	nest(self.parsed.blocks.clone())
+    }
+
+    /// The names of the tags in the note, without their `#` or `:`, and
+    /// the ones in its `tags` property
+    pub fn tags(&self) -> BTreeSet<String> {
+	SNoteSection::tags(&self.parsed.blocks)
+	    .into_iter()
+	    .map(|tag| self.raw_content[tag.content_span()].to_string())
+	    .chain(self.properties().tags)
+	    .collect()
+    }
+
+    /// The note's metadata, from the drawer at its top or right after its
+    /// first headline
+    pub fn properties(&self) -> Properties {
+	let drawer = self.drawer().map(|(_, properties)| properties).unwrap_or_default();
+	Properties::from_pairs(drawer.iter()
+	    .map(|property| (&self.raw_content[property.key.clone()], &self.raw_content[property.value.clone()])))
+    }
+
+    /// Sets `key` in the note's drawer, a new drawer goes after the first
+    /// headline or at the top when the note doesn't start with one
+    pub fn set_property(&mut self, key: &str, value: &str) {
+	let line = format!(":{}: {}", key, value);
+	let line_end = |at: usize| self.raw_content[at..].find('\n').map_or(self.raw_content.len(), |end| at + end);
+	match self.drawer() {
+	    Some((span, properties)) => {
+		let existing = properties.iter()
+		    .find(|property| self.raw_content[property.key.clone()].eq_ignore_ascii_case(key));
+		match existing {
+		    Some(property) => self.replace_range(property.span.clone(), &line),
+		    None => {
+			let after = properties.last().map_or_else(|| line_end(span.start), |last| last.span.end);
+			self.replace_range(after..after, &format!("\n{}", line));
+		    },
+		}
+	    },
+	    None => match self.parsed.blocks.first() {
+		Some(headline @ SNoteSection::Headline{..}) => {
+		    let end = line_end(headline.span().start);
+		    self.replace_range(end..end, &format!("\n:PROPERTIES:\n{}\n:END:", line));
+		},
+		_ => self.replace_range(0..0, &format!(":PROPERTIES:\n{}\n:END:\n", line)),
+	    },
+	}
+    }
+
+    fn drawer(&self) -> Option<(Range<usize>, Vec<Property>)> {
+	let blocks = &self.parsed.blocks;
+	let after_headline = matches!(blocks.first(), Some(SNoteSection::Headline{..}));
+	blocks.iter().take(if after_headline { 2 } else { 1 }).find_map(|block| match block {
+	    SNoteSection::Properties{span, properties} => Some((span.clone(), properties.clone())),
+	    _ => None,
+	})
+    }
+
+    /// The names of the notes the note links to
+    pub fn links(&self) -> Vec<String> {
+	SNoteSection::links(&self.parsed.blocks)
+	    .into_iter()
+	    .map(|link| self.raw_content[link.content_span()].trim().to_string())
+	    .collect()
+    }
+
+    /// What the link or url at byte `pos` points to
+    pub fn link_at(&self, pos: usize) -> Option<LinkTarget> {
+	match SNoteSection::inline_at(&self.parsed.blocks, pos)? {
+	    link @ SNoteSection::Link(_) =>
+		Some(LinkTarget::Note(self.raw_content[link.content_span()].trim().to_string())),
+	    url @ SNoteSection::Url(_) => Some(LinkTarget::Url(self.raw_content[url.span()].to_string())),
+	    _ => None,
+	}
+    }
+
+    /// The name typed so far after a `[[` the cursor at `pos` is in, as a
+    /// byte range that ends at `pos`
+    pub fn unfinished_link_at(&self, pos: usize) -> Option<Range<usize>> {
+	let line = self.raw_content.get(..pos)?.rsplit('\n').next()?;
+	let name = &line[line.rfind("[[")? + 2..];
+	(!name.contains(']')).then(|| pos - name.len()..pos)
+    }
+
+    /// Replaces the unfinished link `name` with a link to `target`, returns
+    /// the position after the link
+    pub fn complete_link(&mut self, name: Range<usize>, target: &str) -> usize {
+	// the closing brackets might already be there
+	let closed = self.raw_content[name.end..].starts_with("]]");
+	let end = if closed { name.end + 2 } else { name.end };
+	let start = name.start;
+	self.replace_range(name.start..end, &format!("{}]]", target));
+	start + target.len() + 2
+    }
+
+    /// Breaks the list item at `pos` in two, the part after `pos` going to
+    /// a new item with the next marker (and an empty checkbox if the item
+    /// has one), and renumbers the items after it. Breaking an empty item
+    /// ends the list instead. Returns where the cursor goes, or None when
+    /// `pos` isn't on a list item's line.
+    pub fn continue_list(&mut self, pos: usize) -> Option<usize> {
+	let sections = self.sections();
+	let (items, index) = SNoteSection::list_item_at(&sections, pos)?;
+	let (marker, task, span) = match &items[index] {
+	    SNoteSection::ListItem{marker, task, span, ..} => (marker, task, span),
+	    _ => return None,
+	};
+	let line_end = self.raw_content[span.start..].find('\n').map_or(self.raw_content.len(), |end| span.start + end);
+	if pos < marker.span.end || pos > line_end {
+	    return None;
+	}
+	let content = task.as_ref().map_or(marker.span.end, |task| task.span.end);
+	if self.raw_content[content..line_end].trim().is_empty() {
+	    let start = span.start;
+	    self.replace_range(start..line_end, "");
+	    return Some(start);
+	}
+	let indent = &self.raw_content[span.start..marker.span.start];
+	let checkbox = if task.is_some() { "[ ] " } else { "" };
+	let mut edits = vec![(pos..pos, format!("\n{}{} {}", indent, marker.kind.next(), checkbox))];
+	if let ListKind::Ordered(number) = marker.kind {
+	    let after = items[index + 1..].iter().map_while(|item| match item {
+		SNoteSection::ListItem{marker, ..} => Some(marker),
+		_ => None,
+	    });
+	    let numbers = after.filter_map(|marker| match marker.kind {
+		ListKind::Ordered(number) => Some((marker.span.clone(), number)),
+		ListKind::Bullet(_) => None,
+	    });
+	    for ((span, current), number) in numbers.zip(number + 2..) {
+		if current != number {
+		    edits.push((span, format!("{}.", number)));
+		}
+	    }
+	}
+	// a single change, so undoing it takes the renumbering back too
+	let end = edits.last().map_or(pos, |(range, _)| range.end);
+	let mut replacement = String::new();
+	let mut at = pos;
+	for (range, text) in &edits {
+	    replacement.push_str(&self.raw_content[at..range.start]);
+	    replacement.push_str(text);
+	    at = range.end;
+	}
+	let cursor = pos + edits[0].1.len();
+	self.replace_range(pos..end, &replacement);
+	Some(cursor)
+    }
+
+    /// The rows of the table with a line at `pos`
+    pub fn table_at(&self, pos: usize) -> Option<&[TableRow]> {
+	self.parsed.blocks.iter().find_map(|block| match block {
+	    SNoteSection::Table{rows, ..} => {
+		let (first, last) = (rows.first()?, rows.last()?);
+		(first.span.start <= pos && pos <= last.span.end).then_some(rows.as_slice())
+	    },
+	    _ => None,
+	})
+    }
+
+    /// Pads the cells of the table at `pos` so its columns line up, like Tab
+    /// in org-mode. Returns where the cell after the one at `pos` starts, or
+    /// None when `pos` isn't in a table.
+    pub fn format_table(&mut self, pos: usize) -> Option<usize> {
+	let text = &self.raw_content;
+	let rows = self.table_at(pos)?;
+	let cells: Vec<Vec<&str>> = rows.iter()
+	    .map(|row| row.cells.iter().map(|cell| text[cell.clone()].trim()).collect())
+	    .collect();
+	let mut widths = vec![1; cells.iter().map(Vec::len).max()?];
+	for (_, cells) in rows.iter().zip(&cells).filter(|(row, _)| !row.separator) {
+	    for (widest, cell) in widths.iter_mut().zip(cells) {
+		*widest = (*widest).max(cell.chars().count());
+	    }
+	}
+	let start = rows[0].span.start;
+	let indent = &text[start..start + text[start..].find('|')?];
+	let line_end = |row: &TableRow| row.span.end - text[row.span.clone()].len()
+	    + text[row.span.clone()].trim_end_matches(['\n', '\r']).len();
+
+	let mut formatted = String::new();
+	// where the content of every cell starts in `formatted`
+	let mut starts: Vec<Vec<usize>> = vec![];
+	for (row, cells) in rows.iter().zip(&cells) {
+	    if !formatted.is_empty() {
+		formatted.push('\n');
+	    }
+	    formatted.push_str(indent);
+	    let mut row_starts = vec![];
+	    if row.separator {
+		let dashes: Vec<_> = widths.iter().map(|width| "-".repeat(width + 2)).collect();
+		formatted.push_str(&format!("|{}|", dashes.join("+")));
+	    } else {
+		for (column, width) in widths.iter().enumerate() {
+		    formatted.push_str("| ");
+		    row_starts.push(formatted.len());
+		    formatted.push_str(&format!("{:<width$} ", cells.get(column).unwrap_or(&""), width = width));
+		}
+		formatted.push('|');
+	    }
+	    starts.push(row_starts);
+	}
+
+	// the cell the cursor is in, counted by the `|`s before it
+	let row = rows.iter().position(|row| pos < row.span.end).unwrap_or(rows.len() - 1);
+	let before = &text[rows[row].span.start..pos.min(line_end(&rows[row]))];
+	let column = before.matches('|').count().saturating_sub(1);
+	let next = starts[row].get(column + 1)
+	    .or_else(|| starts[row + 1..].iter().find_map(|row| row.first()))
+	    .or_else(|| starts[row].get(column))
+	    .or_else(|| starts[row].last())
+	    .copied()
+	    .unwrap_or(0);
+	let end = line_end(&rows[rows.len() - 1]);
+	self.replace_range(start..end, &formatted);
+	Some(start + next)
+    }
+
+    /// The errors the parser recovered from, the sections around them are
+    /// still parsed normally
+    pub fn diagnostics(&self) -> &[Simple<char>] {
+	&self.parsed.diagnostics
+    }
+
     fn update_sections(mut self) -> Self{
-	self.sections = snote()
-	    .parse(self.raw_content.clone())
-            .unwrap_or_else(|_| vec![SNoteSection::Paragraph(0..self.raw_content.len())]);
+	self.parsed = ParsedNote::new(&self.raw_content);
 	self
     }
 }
 
+/// How many bytes the texts `a` and `b` start with in common, given as
+/// their chars along with how many bytes come before each
+fn common_prefix(a: impl Iterator<Item = (usize, char)>, b: impl Iterator<Item = (usize, char)>) -> usize {
+    a.zip(b)
+	.take_while(|((_, x), (_, y))| x == y)
+	.last()
+	.map_or(0, |((end, c), _)| end + c.len_utf8())
+}
+
+impl Edit {
+    /// A single edit with the same effect as applying `self` and then `next`,
+    /// `next` is relative to the text after `self`
+    pub fn then(self, next: Edit) -> Edit {
+	let replaced_end = self.range.start + self.inserted;
+	let start = self.range.start.min(next.range.start);
+	let end = replaced_end.max(next.range.end);
+	// past the replaced text positions only differ by how much it grew
+	let old_end = self.range.end + (end - replaced_end);
+	let new_end = end + next.inserted - next.range.len();
+	Edit{range: start..old_end, inserted: new_end - start}
+    }
+}
+
+impl ParsedNote {
+    fn new(text: &str) -> Self {
+	let (blocks, diagnostics) = parse_blocks(text, 0);
+	Self{blocks, diagnostics}
+    }
+
+    pub(crate) fn blocks(&self) -> &[SNoteSection] {
+	&self.blocks
+    }
+
+    /// `text` is the whole note after `edit` was applied to it
+    pub(crate) fn edited(&mut self, text: &str, edit: &Edit) {
+	if !self.reparse(text, edit) {
+	    *self = Self::new(text);
+	}
+    }
+
+    /// Reparses the blocks touched by `edit` together with the blocks around
+    /// them. The block before can grow into lines that no longer start a
+    /// block of their own (an unmade headline or code fence), and blocks only
+    /// depend on the text from their start, so once the following block comes
+    /// out unchanged nothing after it can change.
+    /// Returns false when the cached blocks couldn't be reused.
+    fn reparse(&mut self, text: &str, edit: &Edit) -> bool {
+	let delta = edit.inserted as isize - edit.range.len() as isize;
+	let moved = |at: usize| (at as isize + delta) as usize;
+	let first = match self.blocks.iter().position(|b| b.span().end >= edit.range.start) {
+	    Some(first) => first.saturating_sub(1),
+	    None => return false,
+	};
+	let touched = self.blocks.iter()
+	    .rposition(|b| b.span().start <= edit.range.end)
+	    .unwrap_or(first)
+	    .max(first);
+	let last = (touched + 1).min(self.blocks.len() - 1);
+	let is_last_block = last + 1 == self.blocks.len();
+
+	// the first block also owns the blank lines at the start of the note
+	let start = if first == 0 { 0 } else { self.blocks[first].span().start };
+	let old_end = self.blocks[last].span().end;
+	let end = if is_last_block { text.len() } else { moved(old_end) };
+	let (reparsed, diagnostics) = parse_blocks(&text[start..end], start);
+
+	if !is_last_block {
+	    let mut unchanged = self.blocks[last].clone();
+	    unchanged.shift(delta);
+	    if reparsed.last() != Some(&unchanged) {
+		return false;
+	    }
+	}
+
+	let mut tail = self.blocks.split_off(last + 1);
+	tail.iter_mut().for_each(|b| b.shift(delta));
+	self.blocks.truncate(first);
+	self.blocks.extend(reparsed);
+	self.blocks.extend(tail);
+
+	let old_end = if is_last_block { usize::MAX } else { old_end };
+	let (before, rest): (Vec<_>, Vec<_>) = self.diagnostics.drain(..)
+	    .filter(|d| !(start..old_end).contains(&d.span().start))
+	    .partition(|d| d.span().start < start);
+	self.diagnostics = before.into_iter()
+	    .chain(diagnostics)
+	    .chain(rest.iter().map(|d| shift_error(d, delta)))
+	    .collect();
+	true
+    }
+}
+
+fn shift_error(error: &Simple<char>, delta: isize) -> Simple<char> {
+    let moved = |sp: Range<usize>| (sp.start as isize + delta) as usize..(sp.end as isize + delta) as usize;
+    let span = moved(error.span());
+    let expected = error.expected().cloned();
+    let found = error.found().cloned();
+    let shifted = match error.reason() {
+	SimpleReason::Unexpected => Simple::expected_input_found(span, expected, found),
+	SimpleReason::Unclosed{span: unclosed, delimiter} => {
+	    let closing = error.expected().flatten().next().copied().unwrap_or(*delimiter);
+	    Simple::unclosed_delimiter(moved(unclosed.clone()), *delimiter, span, closing, found)
+	},
+	SimpleReason::Custom(message) => Simple::custom(span, message),
+    };
+    match error.label() {
+	Some(label) => shifted.with_label(label),
+	None => shifted,
+    }
+}
+
 
 impl FromStr for SNote{
     type Err=Infallible;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
 	Ok(Self {
-	    sections: Default::default(),
-	    raw_content: s.to_string()
+	    parsed: Default::default(),
+	    raw_content: s.to_string(),
+	    history: Default::default(),
 	}.update_sections())
     }
 }
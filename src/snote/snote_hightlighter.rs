@@ -1,9 +1,12 @@
-use std::sync::Arc;
+use std::{cell::RefCell, ops::Range, sync::Arc};
 
-use chumsky::Parser;
-use eframe::{egui::{self, TextBuffer, TextFormat}, epaint::text::TextWrapping};
+use eframe::{egui::{self, TextBuffer, TextFormat, text_edit::{CCursorRange, TextEditState}}, epaint::text::{LayoutSection, TextWrapping, cursor::CCursor}};
 
-use super::{SNoteSection, snote};
+use crate::autocomplete_popup::{AutocompleteOutput, AutocompletePopup, Ranked, rank};
+
+use super::{Change, LinkTarget, Markup, SNote, SNoteSection, TableRow, Task, TaskKind};
+use super::code_highlighter::{TokenKind, highlight};
+use super::snote::Edit;
 
 
 fn simple_text_layout(ui: &egui::Ui, text: &str) -> egui::text::LayoutJob {
@@ -15,46 +18,315 @@ fn simple_text_layout(ui: &egui::Ui, text: &str) -> egui::text::LayoutJob {
         ui.available_width(),
     )
 }
-pub fn snote_layouter(ui: &egui::Ui, text: &str, _wrap_width: f32) -> Arc<egui::Galley> {
-    let job = snote()
-        .parse(text)
-        .map(|sections| {
-            let layout_sections = sections.iter().map(|section| {
-		eframe::epaint::text::LayoutSection{
-		    leading_space: 0.0,
-		    byte_range: section.span(),
-		    format: section.highlight_format(ui)
+/// Lays `text` out using the already parsed `blocks` of the note
+fn snote_layouter(ui: &egui::Ui, text: &str, blocks: &[SNoteSection]) -> Arc<egui::Galley> {
+    // the cache can only be stale if the text was changed behind the note's back
+    let in_sync = blocks.last().is_none_or(|block| block.span().end == text.len());
+    let job = if in_sync {
+	snote_layout_job(ui, text, blocks)
+    } else {
+	simple_text_layout(ui, text)
+    };
+    ui.fonts().layout_job(job)
+}
+
+/// How every part of `text` looks, by the parsed `blocks` of the note
+pub(crate) fn snote_layout_job(ui: &egui::Ui, text: &str, blocks: &[SNoteSection]) -> egui::text::LayoutJob {
+    let layout_sections = blocks.iter()
+	.flat_map(|section| section.layout_sections(ui, text))
+	.collect();
+    let text_wrapping = TextWrapping{
+	max_width: ui.max_rect().width(),
+	..Default::default()
+    };
+    egui::text::LayoutJob{
+	text: text.to_string(),
+	sections: fill_gaps(layout_sections, 0..text.len(), simple_format(ui)),
+	wrap: text_wrapping,
+	..Default::default()
+    }
+}
+
+/// Text that isn't covered by any section (e.g. leading blank lines or the
+/// text around inline markup) isn't laid out at all, so it gets `format`
+fn fill_gaps(sections: Vec<LayoutSection>, range: Range<usize>, format: TextFormat)
+	     -> Vec<LayoutSection> {
+    let mut filled = Vec::with_capacity(sections.len());
+    let mut covered = range.start;
+    let gap = |range: Range<usize>| LayoutSection{
+	leading_space: 0.0,
+	byte_range: range,
+	format: format.clone(),
+    };
+    for section in sections {
+	if section.byte_range.start > covered {
+	    filled.push(gap(covered..section.byte_range.start));
+	}
+	covered = section.byte_range.end;
+	filled.push(section);
+    }
+    if range.end > covered {
+	filled.push(gap(covered..range.end));
+    }
+    filled
+}
+
+fn layout_section(byte_range: Range<usize>, format: TextFormat) -> LayoutSection {
+    LayoutSection{
+	leading_space: 0.0,
+	byte_range,
+	format,
+    }
+}
+
+impl SNoteSection {
+    fn layout_sections(&self, ui: &egui::Ui, text: &str) -> Vec<LayoutSection> {
+	let format = self.highlight_format(ui);
+	let task = self.task()
+	    .map(|task| layout_section(task.span.clone(), Self::task_format(ui, task, &format)));
+	match self {
+	    SNoteSection::Paragraph(span, inlines) => {
+		let inlines = Self::inline_sections(ui, text, inlines, &format);
+		fill_gaps(inlines, span.clone(), format)
+	    },
+	    SNoteSection::ListItem{marker, span, inlines, children, ..} => {
+		// monospace indentation keeps nested items lined up
+		let indent = Some(span.start..marker.span.start)
+		    .filter(|indent| !indent.is_empty())
+		    .map(|indent| layout_section(indent, TextFormat{
+			font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+			..format.clone()
+		    }));
+		let marker = layout_section(marker.span.clone(), Self::list_marker_format(ui, &format));
+		let inlines = indent.into_iter()
+		    .chain(Some(marker))
+		    .chain(task)
+		    .chain(Self::inline_sections(ui, text, inlines, &format))
+		    .collect();
+		fill_gaps(inlines, span.clone(), format)
+		    .into_iter()
+		    .chain(children.iter().flat_map(|child| child.layout_sections(ui, text)))
+		    .collect()
+	    },
+	    SNoteSection::Headline{span, inlines, children, ..} => {
+		let inlines = task.into_iter()
+		    .chain(Self::inline_sections(ui, text, inlines, &format))
+		    .collect();
+		fill_gaps(inlines, span.clone(), format)
+		    .into_iter()
+		    .chain(children.iter().flat_map(|child| child.layout_sections(ui, text)))
+		    .collect()
+	    },
+	    SNoteSection::Markup(_, span) |
+	    SNoteSection::Tag(span) |
+	    SNoteSection::Link(span) |
+	    SNoteSection::Url(span) => vec![layout_section(span.clone(), format)],
+	    SNoteSection::CodeBlock{span, language, code} => {
+		let language = language.clone().map_or("", |language| &text[language]);
+		let tokens = highlight(language, &text[code.clone()])
+		    .into_iter()
+		    .map(|(token, kind)| layout_section(
+			(code.start + token.start)..(code.start + token.end),
+			Self::code_format(ui, kind)
+		    ))
+		    .collect();
+		fill_gaps(tokens, span.clone(), format)
+	    },
+	    SNoteSection::Table{span, rows} => fill_gaps(Self::table_sections(ui, text, rows), span.clone(), format),
+	    SNoteSection::Properties{span, properties} => {
+		let keys = properties.iter()
+		    .flat_map(|property| [
+			layout_section(property.key.clone(), TextFormat{
+			    color: ui.style().visuals.strong_text_color(),
+			    ..format.clone()
+			}),
+			layout_section(property.value.clone(), TextFormat{
+			    color: ui.style().visuals.text_color(),
+			    ..format.clone()
+			}),
+		    ])
+		    .filter(|section| !section.byte_range.is_empty())
+		    .collect();
+		fill_gaps(keys, span.clone(), format)
+	    },
+	}
+    }
+
+    /// Cells are monospace and the `|` after every cell is pushed to the
+    /// width of its column, so the table lines up before it's formatted
+    fn table_sections(ui: &egui::Ui, text: &str, rows: &[TableRow]) -> Vec<LayoutSection> {
+	let width = |cell: &Range<usize>| text[cell.clone()].chars().count();
+	let mut widths: Vec<usize> = vec![];
+	for row in rows {
+	    for (column, cell) in row.cells.iter().enumerate() {
+		match widths.get_mut(column) {
+		    Some(widest) => *widest = (*widest).max(width(cell)),
+		    None => widths.push(width(cell)),
 		}
-            }).collect();
-	    let text_wrapping = TextWrapping{
-		max_width: ui.max_rect().width(),
-		..Default::default()
+	    }
+	}
+	let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+	let char_width = ui.fonts().glyph_width(&font_id, ' ');
+	let header = rows.iter().position(|row| row.separator);
+	let border = TextFormat{
+	    font_id: font_id.clone(),
+	    color: ui.style().visuals.weak_text_color(),
+	    ..Default::default()
+	};
+	let mut sections = vec![];
+	for (i, row) in rows.iter().enumerate() {
+	    let format = match (row.separator, header) {
+		(true, _) => border.clone(),
+		(false, Some(header)) if i < header => TextFormat{
+		    font_id: font_id.clone(),
+		    color: ui.style().visuals.strong_text_color(),
+		    ..Default::default()
+		},
+		(false, _) => TextFormat{font_id: font_id.clone(), ..simple_format(ui)},
 	    };
-            egui::text::LayoutJob{
-		text: text.to_string(),
-		sections: layout_sections,
-		wrap: text_wrapping,
-		..Default::default()
+	    for (cell, widest) in row.cells.iter().zip(&widths) {
+		if !cell.is_empty() {
+		    sections.push(layout_section(cell.clone(), format.clone()));
+		}
+		if text[cell.end..].starts_with(['|', '+']) {
+		    sections.push(LayoutSection{
+			leading_space: (widest - width(cell)) as f32 * char_width,
+			byte_range: cell.end..cell.end + 1,
+			format: border.clone(),
+		    });
+		}
 	    }
+	}
+	fill_gaps(sections, rows[0].span.start..rows[rows.len() - 1].span.end, border)
+    }
 
-        })
-        .ok()
-        .unwrap_or_else(|| simple_text_layout(ui, text));
-    ui.fonts().layout_job(job)
-}
+    /// Tags, links and urls keep the size of the line they're on, so
+    /// they're laid out with the format `around` them
+    fn inline_sections(ui: &egui::Ui, text: &str, inlines: &[SNoteSection], around: &TextFormat)
+		       -> Vec<LayoutSection> {
+	inlines.iter()
+	    .flat_map(|inline| match inline {
+		SNoteSection::Tag(span) => vec![layout_section(span.clone(), Self::tag_format(ui, around))],
+		SNoteSection::Link(span) |
+		SNoteSection::Url(span) => vec![layout_section(span.clone(), Self::link_format(ui, around))],
+		_ => inline.layout_sections(ui, text),
+	    })
+	    .collect()
+    }
+
+    fn link_format(ui: &egui::Ui, around: &TextFormat) -> TextFormat {
+	let color = ui.style().visuals.hyperlink_color;
+	TextFormat{
+	    color,
+	    underline: egui::Stroke::new(1.0, color),
+	    ..around.clone()
+	}
+    }
+
+    fn tag_format(ui: &egui::Ui, around: &TextFormat) -> TextFormat {
+	let visuals = &ui.style().visuals;
+	TextFormat{
+	    color: visuals.hyperlink_color,
+	    background: visuals.faint_bg_color,
+	    underline: egui::Stroke::none(),
+	    ..around.clone()
+	}
+    }
+
+    fn list_marker_format(ui: &egui::Ui, around: &TextFormat) -> TextFormat {
+	TextFormat{
+	    color: ui.style().visuals.strong_text_color(),
+	    ..around.clone()
+	}
+    }
+
+    /// Open tasks stand out from the text around them, done ones fade out
+    fn task_format(ui: &egui::Ui, task: &Task, around: &TextFormat) -> TextFormat {
+	let visuals = &ui.style().visuals;
+	let font_id = match task.kind {
+	    TaskKind::Checkbox => egui::TextStyle::Monospace.resolve(ui.style()),
+	    TaskKind::Keyword => around.font_id.clone(),
+	};
+	let color = match task.done {
+	    true => visuals.weak_text_color(),
+	    false => visuals.hyperlink_color,
+	};
+	TextFormat{ font_id, color, ..around.clone() }
+    }
 
-impl SNoteSection {
     fn highlight_format(&self, ui: &egui::Ui) -> TextFormat {
         match self {
-            SNoteSection::Paragraph(_) => Self::paragraph_format(ui),
-            SNoteSection::Headline(_) => Self::headline_format(ui),
+            SNoteSection::Paragraph(..) => Self::paragraph_format(ui),
+            SNoteSection::ListItem{task, ..} => Self::list_item_format(ui, task.as_ref()),
+            SNoteSection::Headline{level, ..} => Self::headline_format(ui, *level),
+            SNoteSection::Markup(markup, _) => Self::markup_format(ui, *markup),
+            SNoteSection::Tag(_) => Self::tag_format(ui, &simple_format(ui)),
+            SNoteSection::Link(_) |
+            SNoteSection::Url(_) => Self::link_format(ui, &simple_format(ui)),
+            SNoteSection::Properties{..} => TextFormat{
+		font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+		color: ui.style().visuals.weak_text_color(),
+		..Default::default()
+	    },
+            SNoteSection::Table{..} => TextFormat{
+		font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+		..simple_format(ui)
+	    },
+            SNoteSection::CodeBlock{..} => TextFormat{
+		color: ui.style().visuals.weak_text_color(),
+		..Self::code_format(ui, TokenKind::Plain)
+	    },
         }
     }
 
-    fn headline_format(ui: &egui::Ui) -> TextFormat {
+    /// Code is monospace on the code background, colored by what it is
+    fn code_format(ui: &egui::Ui, kind: TokenKind) -> TextFormat {
+	let visuals = &ui.style().visuals;
+	let rgb = |dark: (u8, u8, u8), light: (u8, u8, u8)| {
+	    let (r, g, b) = if visuals.dark_mode { dark } else { light };
+	    egui::Color32::from_rgb(r, g, b)
+	};
+	let color = match kind {
+	    TokenKind::Plain => visuals.text_color(),
+	    TokenKind::Keyword => rgb((198, 120, 221), (150, 50, 170)),
+	    TokenKind::String => rgb((152, 195, 121), (60, 130, 40)),
+	    TokenKind::Number => rgb((209, 154, 102), (170, 90, 20)),
+	    TokenKind::Comment => visuals.weak_text_color(),
+	};
+	TextFormat{
+	    font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+	    color,
+	    background: visuals.code_bg_color,
+	    ..Default::default()
+	}
+    }
+
+    fn markup_format(ui: &egui::Ui, markup: Markup) -> TextFormat {
+	let visuals = &ui.style().visuals;
+	let stroke = egui::Stroke::new(1.0, visuals.text_color());
+	let format = Self::paragraph_format(ui);
+	match markup {
+	    Markup::Bold => TextFormat{ color: visuals.strong_text_color(), ..format },
+	    Markup::Italic => TextFormat{ italics: true, ..format },
+	    Markup::Code => TextFormat{
+		font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+		background: visuals.code_bg_color,
+		..format
+	    },
+	    Markup::Strike => TextFormat{ strikethrough: stroke, ..format },
+	    Markup::Underline => TextFormat{ underline: stroke, ..format },
+	}
+    }
+
+    /// Top level headlines use the heading font, every level below
+    /// shrinks it further towards the body font
+    fn headline_format(ui: &egui::Ui, level: usize) -> TextFormat {
         let color = ui.style().visuals.strong_text_color();
+	let heading = egui::TextStyle::Heading.resolve(ui.style());
+	let body_size = egui::TextStyle::Body.resolve(ui.style()).size;
+	let size = body_size + (heading.size - body_size) / level.max(1) as f32;
         TextFormat {
-            font_id: egui::TextStyle::Heading.resolve(ui.style()),
+            font_id: egui::FontId::new(size, heading.family),
             color,
             underline: egui::Stroke::new(1.0, color),
             ..Default::default()
@@ -64,6 +336,16 @@ impl SNoteSection {
     fn paragraph_format(ui: &egui::Ui) -> TextFormat {
         simple_format(ui)
     }
+
+    fn list_item_format(ui: &egui::Ui, task: Option<&Task>) -> TextFormat {
+	match task {
+	    Some(Task{done: true, ..}) => TextFormat{
+		color: ui.style().visuals.weak_text_color(),
+		..simple_format(ui)
+	    },
+	    _ => simple_format(ui),
+	}
+    }
 }
 
 #[inline]
@@ -75,13 +357,293 @@ fn simple_format(ui: &egui::Ui) -> TextFormat {
     }
 }
 
-pub fn snote_widget(note: &mut dyn TextBuffer) -> impl egui::Widget + '_{
-    |ui: &mut egui::Ui| -> egui::Response{
+pub fn snote_widget(note: &mut SNote) -> SNoteEditor<'_>{
+    SNoteEditor{note, jump_to: None, opened_link: None, link_targets: None}
+}
+
+/// The editor widget for a note, created with [`snote_widget`]
+pub struct SNoteEditor<'n>{
+    note: &'n mut SNote,
+    jump_to: Option<usize>,
+    opened_link: Option<&'n mut Option<LinkTarget>>,
+    link_targets: Option<&'n dyn Fn() -> Vec<String>>,
+}
+
+/// The name of the link being typed and where the names it can be completed
+/// with are shown
+/// The link targets ranked for the name they were ranked by
+type RankedTargets = (String, Vec<Ranked<String>>);
+
+#[derive(Debug, Clone)]
+struct LinkCompletion{
+    name: Range<usize>,
+    anchor: egui::Pos2,
+}
+
+impl<'n> SNoteEditor<'n> {
+    /// Moves the cursor to the byte offset `pos` of the note and scrolls to it
+    pub fn jump_to(self, pos: Option<usize>) -> Self{
+	Self{jump_to: pos, ..self}
+    }
+
+    /// Links and urls are followed with Ctrl+click, what a followed link
+    /// points to is put in `link`
+    pub fn opened_link(self, link: &'n mut Option<LinkTarget>) -> Self{
+	Self{opened_link: Some(link), ..self}
+    }
+
+    /// Typing `[[` pops up the names `targets` returns, choosing one
+    /// completes the link
+    pub fn link_targets(self, targets: &'n dyn Fn() -> Vec<String>) -> Self{
+	Self{link_targets: Some(targets), ..self}
+    }
+}
+
+impl egui::Widget for SNoteEditor<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+	let SNoteEditor{note, jump_to, opened_link, link_targets} = self;
 	egui::ScrollArea::both().show(ui, |ui|{
-	    let te = ui.add(egui::TextEdit::multiline(note)
-			    .layouter(&mut snote_layouter));
-	    ui.scroll_to_cursor(None);
-	    te
+	    let id = ui.make_persistent_id("snote_editor");
+	    let popup_id = id.with("::link_ac");
+	    // the popup goes first so it gets the arrow keys and enter
+	    let completed = link_targets.and_then(|targets| complete_link(ui, popup_id, note, targets));
+	    // the note's own history replaces the editor's, so it isn't lost
+	    // when another note is shown
+	    let edited = completed
+		.or_else(|| undo_or_redo(ui, id, note))
+		.or_else(|| continue_list(ui, id, note))
+		.or_else(|| format_table(ui, id, note));
+	    let jump_to = edited.or(jump_to);
+	    // the note could have changed since `pos` was found, so it's only a hint
+	    let jump_to = jump_to.and_then(|pos| note.raw_content.get(..pos)).map(|before| {
+		let cursor = CCursor::new(before.chars().count());
+		let mut state = TextEditState::load(ui.ctx(), id).unwrap_or_default();
+		state.set_ccursor_range(Some(CCursorRange::one(cursor)));
+		state.store(ui.ctx(), id);
+		ui.memory().request_focus(id);
+		cursor
+	    });
+	    // Tab stays in the editor to format tables instead of moving the focus
+	    let in_table = single_cursor(ui, id, note).is_some_and(|pos| note.table_at(pos).is_some());
+	    let SNote{raw_content, parsed, history} = &mut *note;
+	    let edits = RefCell::new(None);
+	    let mut text = TrackedText{text: raw_content, edits: &edits, changes: vec![]};
+	    let mut layouter = |ui: &egui::Ui, text: &str, _wrap_width: f32| {
+		if let Some(edit) = edits.take() {
+		    parsed.edited(text, &edit);
+		}
+		snote_layouter(ui, text, parsed.blocks())
+	    };
+	    let mut te = egui::TextEdit::multiline(&mut text)
+		.id(id)
+		.lock_focus(in_table)
+		.layouter(&mut layouter)
+		.show(ui);
+	    text.changes.into_iter().for_each(|change| history.record(change));
+	    if let Some(edit) = edits.take() {
+		parsed.edited(raw_content, &edit);
+	    }
+	    match jump_to {
+		Some(cursor) => {
+		    let cursor_rect = te.galley.pos_from_cursor(&te.galley.from_ccursor(cursor));
+		    ui.scroll_to_rect(cursor_rect.translate(te.text_draw_pos.to_vec2()),
+				      Some(egui::Align::Center));
+		},
+		None => ui.scroll_to_cursor(None),
+	    }
+	    let toggle_key = te.response.has_focus() &&
+		ui.input_mut().consume_key(egui::Modifiers::CTRL, egui::Key::Space);
+	    let cursor = te.cursor_range
+		.map(|c| raw_content.byte_index_from_char_index(c.primary.ccursor.index));
+	    let pointed = ui.input().pointer.hover_pos()
+		.filter(|_| te.response.hovered())
+		.map(|pos| te.galley.cursor_from_pos(pos - te.text_draw_pos))
+		.map(|pointed| raw_content.byte_index_from_char_index(pointed.ccursor.index));
+	    let following = ui.input().modifiers.command;
+	    if let Some(link) = pointed.filter(|_| following).and_then(|pos| note.link_at(pos)) {
+		ui.output().cursor_icon = egui::CursorIcon::PointingHand;
+		if let (true, Some(opened)) = (te.response.clicked(), opened_link) {
+		    *opened = Some(link);
+		}
+	    }
+	    if let Some(pos) = cursor {
+		// a click only toggles when it lands inside the box or keyword,
+		// so the cursor can still be placed right before or after it
+		let clicked = te.response.clicked() && !following;
+		if (toggle_key || clicked) && toggle_task(note, pos, clicked) {
+		    te.response.mark_changed();
+		}
+	    }
+	    if edited.is_some() {
+		te.response.mark_changed();
+	    }
+	    if let (Some(range), Some(_), true) = (te.cursor_range, link_targets, te.response.has_focus()) {
+		let ccursor = range.primary.ccursor;
+		let pos = note.raw_content.byte_index_from_char_index(ccursor.index);
+		match note.unfinished_link_at(pos) {
+		    Some(name) => {
+			let at = te.galley.pos_from_cursor(&te.galley.from_ccursor(ccursor));
+			let anchor = at.translate(te.text_draw_pos.to_vec2()).left_bottom();
+			ui.memory().data.insert_temp(popup_id, LinkCompletion{name, anchor});
+			if te.response.changed() {
+			    ui.memory().open_popup(popup_id);
+			}
+		    },
+		    None if ui.memory().is_popup_open(popup_id) => ui.memory().close_popup(),
+		    None => (),
+		}
+	    }
+	    if te.response.lost_focus() && ui.memory().is_popup_open(popup_id) {
+		ui.memory().close_popup();
+	    }
+	    te.response
 	}).inner
     }
 }
+
+/// Ctrl+Z undoes the last change to the focused note, Ctrl+Shift+Z and
+/// Ctrl+Y redo it, returns where the cursor should go
+fn undo_or_redo(ui: &egui::Ui, id: egui::Id, note: &mut SNote) -> Option<usize> {
+    use egui::{Key, Modifiers};
+    if !ui.memory().has_focus(id) {
+	return None;
+    }
+    let mut input = ui.input_mut();
+    if input.consume_key(Modifiers::CTRL | Modifiers::SHIFT, Key::Z) ||
+	input.consume_key(Modifiers::CTRL, Key::Y) {
+	drop(input);
+	note.redo()
+    } else if input.consume_key(Modifiers::CTRL, Key::Z) {
+	drop(input);
+	note.undo()
+    } else {
+	None
+    }
+}
+
+/// The byte offset of the focused editor's cursor, unless something is
+/// selected
+fn single_cursor(ui: &egui::Ui, id: egui::Id, note: &SNote) -> Option<usize> {
+    if !ui.memory().has_focus(id) {
+	return None;
+    }
+    let range = TextEditState::load(ui.ctx(), id)?.ccursor_range()?;
+    (range.primary.index == range.secondary.index)
+	.then(|| note.raw_content.byte_index_from_char_index(range.primary.index))
+}
+
+/// Where the cursor is when `key` was pressed without modifiers
+fn cursor_on_key(ui: &egui::Ui, id: egui::Id, note: &SNote, key: egui::Key) -> Option<usize> {
+    let pressed = ui.input().key_pressed(key) && ui.input().modifiers.is_none();
+    pressed.then(|| single_cursor(ui, id, note)).flatten()
+}
+
+/// Enter on a list item starts the next item instead of a plain line,
+/// returns where the cursor should go
+fn continue_list(ui: &egui::Ui, id: egui::Id, note: &mut SNote) -> Option<usize> {
+    let pos = cursor_on_key(ui, id, note, egui::Key::Enter)?;
+    let cursor = note.continue_list(pos)?;
+    ui.input_mut().consume_key(egui::Modifiers::NONE, egui::Key::Enter);
+    Some(cursor)
+}
+
+/// Tab in a table lines its columns up and moves to the next cell,
+/// returns where the cursor should go
+fn format_table(ui: &egui::Ui, id: egui::Id, note: &mut SNote) -> Option<usize> {
+    let pos = cursor_on_key(ui, id, note, egui::Key::Tab)?;
+    let cursor = note.format_table(pos)?;
+    ui.input_mut().consume_key(egui::Modifiers::NONE, egui::Key::Tab);
+    Some(cursor)
+}
+
+/// Shows the names the link being typed can be completed with, returns
+/// where the cursor should go once one was chosen
+fn complete_link(ui: &egui::Ui, popup_id: egui::Id, note: &mut SNote,
+		 targets: &dyn Fn() -> Vec<String>) -> Option<usize> {
+    // ranking every note takes a while, it's only done again when the name
+    // changes while the popup is open
+    let ranked_id = popup_id.with("::ranked");
+    if !ui.memory().is_popup_open(popup_id) {
+	ui.memory().data.remove::<RankedTargets>(ranked_id);
+	return None;
+    }
+    let LinkCompletion{name, anchor} = ui.memory().data.get_temp(popup_id)?;
+    let typed = note.raw_content.get(name.clone())?.to_string();
+    let ranked = match ui.memory().data.get_temp::<RankedTargets>(ranked_id) {
+	Some((ranked_for, ranked)) if ranked_for == typed => ranked,
+	_ => {
+	    let ranked = rank(targets(), &typed);
+	    ui.memory().data.insert_temp(ranked_id, (typed, ranked.clone()));
+	    ranked
+	},
+    };
+    let popup = AutocompletePopup::ranked_with_id(ranked, popup_id);
+    if popup.is_empty() {
+	// nothing to choose, enter should start a new line
+	ui.memory().close_popup();
+	return None;
+    }
+    match popup.show_at(ui, anchor)? {
+	AutocompleteOutput::Chosen(target) => Some(note.complete_link(name, &target)),
+	AutocompleteOutput::Marked(_) => None,
+    }
+}
+
+/// Hands the text to the `TextEdit` while remembering what it changed, so
+/// only the touched blocks of the note need to be parsed again and the
+/// changes can be undone
+struct TrackedText<'t>{
+    text: &'t mut String,
+    edits: &'t RefCell<Option<Edit>>,
+    changes: Vec<Change>,
+}
+
+impl TrackedText<'_> {
+    fn record(&self, edit: Edit) {
+	let mut edits = self.edits.borrow_mut();
+	*edits = Some(match edits.take() {
+	    Some(earlier) => earlier.then(edit),
+	    None => edit,
+	});
+    }
+}
+
+impl AsRef<str> for TrackedText<'_> {
+    fn as_ref(&self) -> &str {
+	self.text.as_str()
+    }
+}
+
+impl TextBuffer for TrackedText<'_> {
+    fn is_mutable(&self) -> bool {
+	true
+    }
+
+    fn insert_text(&mut self, text: &str, char_index: usize) -> usize {
+	let at = self.text.byte_index_from_char_index(char_index);
+	self.record(Edit{range: at..at, inserted: text.len()});
+	self.changes.push(Change::new(at, "", text));
+	self.text.insert_text(text, char_index)
+    }
+
+    fn delete_char_range(&mut self, char_range: Range<usize>) {
+	let range = self.text.byte_index_from_char_index(char_range.start)
+	    ..self.text.byte_index_from_char_index(char_range.end);
+	self.changes.push(Change::new(range.start, &self.text[range.clone()], ""));
+	self.record(Edit{range, inserted: 0});
+	self.text.delete_char_range(char_range)
+    }
+}
+
+/// Flips the task under the byte position `pos` by rewriting it in place,
+/// returns whether anything was changed
+fn toggle_task(note: &mut SNote, pos: usize, strictly_inside: bool) -> bool {
+    let sections = note.sections();
+    match SNoteSection::task_at(&sections, pos) {
+	Some(task) if !strictly_inside || (task.span.start < pos && pos < task.span.end) => {
+	    note.replace_range(task.span.clone(), task.toggled());
+	    true
+	},
+	_ => false,
+    }
+}
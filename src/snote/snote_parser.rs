@@ -1,16 +1,149 @@
 use std::ops::Range;
 
-use chumsky::{prelude::*, text::newline};
+use chumsky::{prelude::*, text::newline, Stream};
 
 fn line_end() -> impl Parser<char, Option<char>, Error = Simple<char>> {
     newline().to(Some('\n')).or(end().to(None))
 }
 
+fn headline_stars() -> impl Parser<char, usize, Error = Simple<char>> {
+    just('*')
+        .repeated()
+        .at_least(1)
+        .then_ignore(just(' '))
+        .map(|stars| stars.len())
+}
+
+fn todo_keyword() -> impl Parser<char, Task, Error = Simple<char>> {
+    just("TODO").to(false)
+	.or(just("DONE").to(true))
+	.then_ignore(not(filter(|c: &char| !c.is_whitespace())))
+	.map_with_span(|done, span| Task{done, kind: TaskKind::Keyword, span})
+}
+
 fn headline() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
-    just("* ")
-        .ignore_then(take_until(line_end()))
-        .map_with_span(|_, sp|
-		       SNoteSection::Headline(sp.start..sp.end))
+    // like in paragraphs, a `#` inside a word doesn't start a tag
+    let text = word().to(vec![])
+	.or(filter(|c: &char| !is_line_break(c)).to(vec![]));
+    headline_stars()
+	.then(todo_keyword().or_not())
+	.then(choice((
+	    headline_tags(),
+	    hash_tag().map(|tag| vec![tag]),
+	    wiki_link().map(|link| vec![link]),
+	    url().map(|url| vec![url]),
+	    text,
+	)).repeated().flatten())
+        .then_ignore(line_end())
+        .map_with_span(|((level, task), inlines), sp|
+		       SNoteSection::Headline{level, task, span: sp, inlines, children: vec![]})
+}
+
+fn tag_name() -> impl Parser<char, (), Error = Simple<char>> {
+    filter(|c: &char| c.is_alphabetic())
+	.then(filter(|c: &char| c.is_alphanumeric() || *c == '_' || *c == '-').repeated())
+	.ignored()
+}
+
+/// `#tag` anywhere in a line, tags start with a letter so `#1` isn't one
+fn hash_tag() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    just('#')
+	.then(tag_name())
+	.map_with_span(|_, span| SNoteSection::Tag(span))
+}
+
+/// `[[note name]]`, a link to another note
+fn wiki_link() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    filter(|c: &char| *c != '[' && *c != ']' && !is_line_break(c))
+	.repeated()
+	.at_least(1)
+	.delimited_by(just("[["), just("]]"))
+	.map_with_span(|_, span| SNoteSection::Link(span))
+}
+
+fn is_url_char(c: &char) -> bool {
+    !c.is_whitespace() && !"<>\"".contains(*c)
+}
+
+/// Punctuation that usually ends the sentence around a url rather than the url
+fn is_trailing_punctuation(c: &char) -> bool {
+    ".,;:!?)]'".contains(*c)
+}
+
+/// `http://`, `https://` or `file:` and everything up to the next whitespace,
+/// except for punctuation at the end
+fn url() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    let plain = filter(|c: &char| is_url_char(c) && !is_trailing_punctuation(c));
+    // punctuation is only part of the url when more of it follows
+    let inner_punctuation = filter(is_trailing_punctuation)
+	.repeated()
+	.at_least(1)
+	.then(plain)
+	.ignored();
+    choice((just("https://"), just("http://"), just("file:")))
+	.then(plain.ignored().or(inner_punctuation).repeated().at_least(1))
+	.map_with_span(|_, span| SNoteSection::Url(span))
+}
+
+/// `:tag1:tag2:` at the end of a headline, every tag's span starts at the
+/// colon before it
+fn headline_tags() -> impl Parser<char, Vec<SNoteSection>, Error = Simple<char>> {
+    just(':')
+	.then(tag_name())
+	.map_with_span(|_, span| SNoteSection::Tag(span))
+	.repeated()
+	.at_least(1)
+	.then_ignore(just(':'))
+	.then_ignore(one_of(" \t").repeated())
+	.then_ignore(line_end().rewind())
+}
+
+fn checkbox() -> impl Parser<char, Task, Error = Simple<char>> {
+    one_of(" xX")
+	.delimited_by(just('['), just(']'))
+	.map_with_span(|mark, span| Task{done: mark != ' ', kind: TaskKind::Checkbox, span})
+}
+
+/// `-`, `+` or `1.` and the space after it, indented by any whitespace
+fn list_marker() -> impl Parser<char, ListMarker, Error = Simple<char>> {
+    let bullet = one_of("-+").map(ListKind::Bullet);
+    let number = filter(char::is_ascii_digit)
+	.repeated()
+	.at_least(1)
+	.at_most(9)
+	.collect::<String>()
+	.then_ignore(just('.'))
+	.map(|number| ListKind::Ordered(number.parse().unwrap_or_default()));
+    one_of(" \t").repeated()
+	.ignore_then(bullet.or(number).map_with_span(|kind, span| ListMarker{kind, span}))
+	.then_ignore(just(' '))
+}
+
+/// `[]` or `[?]`, a checkbox with a typo in it rather than text in brackets
+fn malformed_checkbox() -> impl Parser<char, (), Error = Simple<char>> {
+    filter(|c: &char| !c.is_alphanumeric() && !"[] ".contains(*c) && !is_line_break(c))
+	.or_not()
+	.delimited_by(just('['), just(']'))
+	.ignored()
+}
+
+/// `- text`, `1. text` or `- [ ] text`. A typo in the checkbox is reported
+/// instead of silently becoming text, any other `[` just starts the text,
+/// like in `- [draft] idea`.
+fn list_item() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    let task = checkbox().then_ignore(just(' ').or_not()).map(Some)
+	.or(not(malformed_checkbox()).to(None));
+    list_marker()
+	.then(task)
+	.then(inline().repeated())
+	.then_ignore(line_end())
+	.map_with_span(|((marker, task), inlines), span| SNoteSection::ListItem{
+	    marker,
+	    task,
+	    span,
+	    inlines: inlines.into_iter().flatten().collect(),
+	    children: vec![],
+	})
 }
 
 fn newlines_or_end(amount: usize) -> impl Parser<char, usize, Error = Simple<char>> {
@@ -27,30 +160,432 @@ fn newlines_or_end(amount: usize) -> impl Parser<char, usize, Error = Simple<cha
 			 Err(Simple::custom(span, format!("not enough newlines ({})", nl_count)))
 		 })
 }
+
+fn blank_lines() -> impl Parser<char, usize, Error = Simple<char>> {
+    newlines_or_end(0).or_not().map(Option::unwrap_or_default)
+}
+
+/// Succeeds without consuming anything, but only where `parser` doesn't match
+fn not<O>(parser: impl Parser<char, O, Error = Simple<char>>)
+	  -> impl Parser<char, (), Error = Simple<char>> {
+    parser.rewind().or_not()
+	.try_map(|found, span| match found {
+	    Some(_) => Err(Simple::custom(span, "unexpected input")),
+	    None => Ok(()),
+	})
+}
+
+fn is_line_break(c: &char) -> bool {
+    *c == '\n' || *c == '\r'
+}
+
+/// `marker`text`marker`, where the text doesn't start or end with whitespace
+/// and the closing marker isn't followed by a letter, like org-mode's emphasis
+fn markup(marker: char, kind: Markup) -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    just(marker)
+	.ignore_then(filter(move |c: &char| *c != marker && !is_line_break(c))
+		     .repeated()
+		     .at_least(1))
+	.then_ignore(just(marker))
+	.then_ignore(not(filter(|c: &char| c.is_alphanumeric())))
+	.try_map(move |content, span| {
+	    let trimmed = |c: Option<&char>| c.is_some_and(|c| !c.is_whitespace());
+	    if trimmed(content.first()) && trimmed(content.last()) {
+		Ok(SNoteSection::Markup(kind, span))
+	    } else {
+		Err(Simple::custom(span, "markup can't be padded with whitespace"))
+	    }
+	})
+}
+
+/// A word and whatever sticks to it, markup characters inside a word
+/// (snake_case, a/b paths) are plain text. A `[` or a url or tag right
+/// after punctuation (`foo,[[bar]]`, `see:https://x.org`) ends the word.
+fn word() -> impl Parser<char, (), Error = Simple<char>> {
+    let alphanumeric = || filter(|c: &char| c.is_alphanumeric()).repeated().at_least(1);
+    let punctuation = || filter(|c: &char| !c.is_whitespace() && !c.is_alphanumeric() && *c != '[');
+    let no_inline = || not(url().ignored().or(hash_tag().ignored()));
+    let punctuations = || punctuation().then(no_inline().then(punctuation()).repeated());
+    alphanumeric()
+	.then(punctuations().then(no_inline()).then(alphanumeric()).repeated())
+	.then(punctuations().or_not())
+	.ignored()
+}
+
+fn inline() -> impl Parser<char, Option<SNoteSection>, Error = Simple<char>> {
+    let markup = choice((
+	markup('*', Markup::Bold),
+	markup('/', Markup::Italic),
+	markup('`', Markup::Code),
+	markup('~', Markup::Strike),
+	markup('_', Markup::Underline),
+    ));
+    markup.map(Some)
+	.or(hash_tag().map(Some))
+	.or(wiki_link().map(Some))
+	.or(url().map(Some))
+	.or(word().to(None))
+	.or(filter(|c: &char| !is_line_break(c)).to(None))
+}
+
+fn backticks() -> impl Parser<char, (), Error = Simple<char>> {
+    just("```").ignored()
+}
+
+fn begin_src() -> impl Parser<char, (), Error = Simple<char>> {
+    just("#+begin_src").or(just("#+BEGIN_SRC")).ignored()
+}
+
+fn end_src() -> impl Parser<char, (), Error = Simple<char>> {
+    just("#+end_src").or(just("#+END_SRC")).ignored()
+}
+
+/// The line opening a code block with `fence`, gives the span of the
+/// language after it and where the code starts. A backtick further on
+/// means the line is inline code instead.
+fn code_opening(fence: impl Parser<char, (), Error = Simple<char>>)
+		-> impl Parser<char, (Option<SnoteSpan>, usize), Error = Simple<char>> {
+    let language = filter(|c: &char| !c.is_whitespace() && *c != '`')
+	.repeated()
+	.at_least(1)
+	.map_with_span(|_, span: SnoteSpan| span);
+    fence
+	.ignore_then(one_of(" \t").repeated())
+	.ignore_then(language.or_not())
+	.then_ignore(filter(|c: &char| !is_line_break(c) && *c != '`').repeated())
+	.then_ignore(line_end())
+	.map_with_span(|language, span: SnoteSpan| (language, span.end))
+}
+
+/// The lines between `open` and `close`, blank lines included. A block
+/// that isn't closed runs to the end of the note, so like every other
+/// block it only depends on the text after its start.
+fn fenced<C: Parser<char, (), Error = Simple<char>>>(open: impl Parser<char, (), Error = Simple<char>>,
+						    close: impl Fn() -> C)
+						    -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    let closing = || close()
+	.then(one_of(" \t").repeated())
+	.then(line_end())
+	.map_with_span(|_, span: SnoteSpan| span.start);
+    let code_line = not(closing())
+	.ignore_then(filter(|c: &char| !is_line_break(c)).repeated().then(newline()).ignored()
+		     .or(filter(|c: &char| !is_line_break(c)).repeated().at_least(1).then(end()).ignored()));
+    code_opening(open)
+	.then_ignore(code_line.repeated())
+	.then(closing().or(end().map_with_span(|_, span: SnoteSpan| span.start)))
+	.map_with_span(|((language, start), end), span| SNoteSection::CodeBlock{span, language, code: start..end})
+}
+
+/// ` ```lang ` or `#+begin_src lang` up to ` ``` ` or `#+end_src`
+fn code_block() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    fenced(backticks(), backticks).or(fenced(begin_src(), end_src))
+}
+
+/// `| a | b |`, or a `|---+---|` separator whose cells are the dashes
+/// between the `|` or `+`. The closing `|` can be left out while typing.
+fn table_row() -> impl Parser<char, TableRow, Error = Simple<char>> {
+    let cell = |chars| none_of(chars).repeated().map_with_span(|chars: Vec<char>, span| (chars, span));
+    let separator = one_of(" \t:").repeated()
+	.ignore_then(just('-'))
+	.rewind()
+	.ignore_then(cell("|+\r\n").separated_by(one_of("|+")))
+	.try_map(|cells, span| match cells.iter().flat_map(|(chars, _)| chars).all(|c| "-: \t".contains(*c)) {
+	    true => Ok(cells),
+	    false => Err(Simple::custom(span, "not a table separator")),
+	})
+	.then_ignore(line_end())
+	.map(|cells| (true, cells));
+    let cells = cell("|\r\n")
+	.separated_by(just('|'))
+	.then_ignore(line_end())
+	.map(|cells| (false, cells));
+    one_of(" \t").repeated()
+	.ignore_then(just('|'))
+	.ignore_then(separator.or(cells))
+	.map_with_span(|(separator, mut cells), span| {
+	    // the blank after the closing `|` isn't a cell
+	    if cells.last().is_some_and(|(chars, _)| chars.iter().all(|c| c.is_whitespace())) {
+		cells.pop();
+	    }
+	    TableRow{span, separator, cells: cells.into_iter().map(|(_, span)| span).collect()}
+	})
+}
+
+/// A `:NAME:` line in any case
+fn drawer_line(name: &'static str) -> impl Parser<char, (), Error = Simple<char>> {
+    filter(char::is_ascii_alphabetic)
+	.repeated()
+	.at_least(1)
+	.collect::<String>()
+	.try_map(move |word, span| match word.eq_ignore_ascii_case(name) {
+	    true => Ok(()),
+	    false => Err(Simple::custom(span, format!("expected :{}:", name))),
+	})
+	.delimited_by(just(':'), just(':'))
+	.then(one_of(" \t").repeated())
+	.then(line_end())
+	.ignored()
+}
+
+/// `:key: value`, the value is the rest of the line
+fn property() -> impl Parser<char, Property, Error = Simple<char>> {
+    not(drawer_line("END"))
+	.ignore_then(just(':'))
+	.ignore_then(none_of(": \t\r\n").repeated().at_least(1).map_with_span(|_, span: SnoteSpan| span))
+	.then_ignore(just(':'))
+	.then_ignore(none_of("\r\n").repeated())
+	.map_with_span(|key, span: SnoteSpan| Property{value: (key.end + 1)..span.end, key, span})
+	.then_ignore(line_end())
+}
+
+/// `:PROPERTIES:`, `:key: value` lines and `:END:`, which can be left out
+fn properties() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    drawer_line("PROPERTIES")
+	.ignore_then(property().repeated())
+	.then_ignore(drawer_line("END").or_not())
+	.map_with_span(|properties, span| SNoteSection::Properties{span, properties})
+}
+
+fn table() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    table_row()
+	.repeated()
+	.at_least(1)
+	.map_with_span(|rows, span| SNoteSection::Table{span, rows})
+}
+
+/// A single non empty line which doesn't start another kind of section
+fn plain_line() -> impl Parser<char, Vec<SNoteSection>, Error = Simple<char>> {
+    not(headline_stars())
+	.ignore_then(not(list_marker()))
+	.ignore_then(not(one_of(" \t").repeated().then(just('|'))))
+	.ignore_then(not(drawer_line("PROPERTIES")))
+	.ignore_then(not(code_opening(backticks()).or(code_opening(begin_src()))))
+	.ignore_then(inline().repeated().at_least(1))
+	.then_ignore(line_end())
+	.map(|inlines| inlines.into_iter().flatten().collect())
+}
+
+fn paragraph() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
+    plain_line()
+	.repeated()
+	.at_least(1)
+	.flatten()
+	.map_with_span(|inlines, sp| SNoteSection::Paragraph(sp, inlines))
+}
+
+/// A single section and the blank lines after it, a line that doesn't parse
+/// is reported and kept as a plain paragraph so the rest of the note survives
 fn block() -> impl Parser<char, SNoteSection, Error = Simple<char>> {
-    let block_separator = newlines_or_end(2).map_with_span(|_, s|s).rewind();
-    any().rewind()
-	.ignore_then(take_until(block_separator))
-	.map_with_span(|(_, pad_span), sp| SNoteSection::Paragraph(sp.start..pad_span.end))
+    choice((code_block(), headline(), list_item(), table(), properties(), paragraph()))
+	.recover_with(skip_until(['\n'], |sp| SNoteSection::Paragraph(sp, vec![])).consume_end())
+	.then_ignore(blank_lines())
+	.map_with_span(|mut section, sp: SnoteSpan| {
+	    section.span_mut().end = sp.end;
+	    section
+	})
 }
 
+/// The flat list of sections, headlines don't own their paragraphs yet
 fn blocks() -> impl Parser<char, Vec<SNoteSection>, Error = Simple<char>> {
-    block().map(|b|[b])
-	.separated_by(newlines_or_end(2))
-	.flatten()
+    block().repeated()
 }
 
+/// The whole note as a tree of sections, use `parse_recovery` to get the
+/// sections together with the errors that were recovered from
 pub fn snote() -> impl Parser<char, Vec<SNoteSection>, Error = Simple<char>> {
-    headline().chain(
-	blocks()
-    ).padded().or(end().to(Default::default()))
+    flat_snote().map(nest)
+}
+
+fn flat_snote() -> impl Parser<char, Vec<SNoteSection>, Error = Simple<char>> {
+    newline().repeated()
+	.ignore_then(blocks())
+	.then_ignore(end())
+}
+
+/// Parses the flat blocks of `text` as if it was found at byte `offset` of a
+/// note. Unlike parsing a `&str` directly (which counts chars), every span
+/// here is a byte range, so sections can slice the note and lay it out
+pub(crate) fn parse_blocks(text: &str, offset: usize) -> (Vec<SNoteSection>, Vec<Simple<char>>) {
+    let eoi = offset + text.len();
+    let chars = text.char_indices()
+	.map(move |(i, c)| (c, (offset + i)..(offset + i + c.len_utf8())));
+    let (blocks, errors) = flat_snote().parse_recovery(Stream::from_iter(eoi..eoi, chars));
+    (blocks.unwrap_or_default(), errors)
+}
+
+/// Parses the markup, tags, links and urls of `text` found at byte `offset`
+/// of a note, for text like table cells whose inlines aren't parsed with it
+pub(crate) fn parse_inlines(text: &str, offset: usize) -> Vec<SNoteSection> {
+    let eoi = offset + text.len();
+    let chars = text.char_indices()
+	.map(move |(i, c)| (c, (offset + i)..(offset + i + c.len_utf8())));
+    inline().repeated()
+	.then_ignore(end())
+	.parse(Stream::from_iter(eoi..eoi, chars))
+	.map(|inlines| inlines.into_iter().flatten().collect())
+	.unwrap_or_default()
+}
+
+/// Turns a flat list of sections into a tree where every headline owns
+/// everything that follows it until a headline of the same or a higher level,
+/// and list items own the items below them that are indented further
+pub(crate) fn nest(flat: Vec<SNoteSection>) -> Vec<SNoteSection> {
+    fn push(siblings: &mut Vec<SNoteSection>, section: SNoteSection) {
+	match siblings.last_mut() {
+	    Some(item @ ListItem{..}) if matches!(section, ListItem{..}) && item.indent() < section.indent() => {
+		if let ListItem{children, ..} = item {
+		    push(children, section);
+		}
+	    },
+	    _ => siblings.push(section),
+	}
+    }
+    fn attach(open: &mut [SNoteSection], roots: &mut Vec<SNoteSection>, section: SNoteSection) {
+	match open.last_mut() {
+	    Some(Headline{children, ..}) => push(children, section),
+	    _ => push(roots, section),
+	}
+    }
+    let mut roots = vec![];
+    let mut open: Vec<SNoteSection> = vec![];
+    for section in flat {
+	if let Headline{level, ..} = section {
+	    while open.last().is_some_and(|h| h.level() >= level) {
+		let closed = open.pop().unwrap();
+		attach(&mut open, &mut roots, closed);
+	    }
+	    open.push(section);
+	} else {
+	    attach(&mut open, &mut roots, section);
+	}
+    }
+    while let Some(closed) = open.pop() {
+	attach(&mut open, &mut roots, closed);
+    }
+    roots
 }
 
 type SnoteSpan = Range<usize>;
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum SNoteSection {
-    Paragraph(SnoteSpan),
-    Headline(SnoteSpan),
+    /// A block of text and the inline markup inside it
+    Paragraph(SnoteSpan, Vec<SNoteSection>),
+    Headline{
+	level: usize,
+	task: Option<Task>,
+	span: SnoteSpan,
+	/// the tags, links and urls on the headline's line
+	inlines: Vec<SNoteSection>,
+	children: Vec<SNoteSection>,
+    },
+    /// A single list line, owning the items nested below it
+    ListItem{
+	marker: ListMarker,
+	task: Option<Task>,
+	span: SnoteSpan,
+	/// the markup, tags, links and urls on the item's line
+	inlines: Vec<SNoteSection>,
+	children: Vec<SNoteSection>,
+    },
+    Markup(Markup, SnoteSpan),
+    /// `#tag` inline or `:tag` of a headline's tags
+    Tag(SnoteSpan),
+    /// `[[note name]]`
+    Link(SnoteSpan),
+    /// `https://…` or `file:…`
+    Url(SnoteSpan),
+    /// Fenced code, `code` is the lines between the fences
+    CodeBlock{
+	span: SnoteSpan,
+	language: Option<SnoteSpan>,
+	code: SnoteSpan,
+    },
+    Table{
+	span: SnoteSpan,
+	rows: Vec<TableRow>,
+    },
+    /// A `:PROPERTIES:` drawer
+    Properties{
+	span: SnoteSpan,
+	properties: Vec<Property>,
+    },
+}
+
+/// A `:key: value` line of a drawer, the value isn't trimmed
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Property {
+    pub span: SnoteSpan,
+    pub key: SnoteSpan,
+    pub value: SnoteSpan,
+}
+
+/// A line of a table, `cells` are the spans between its `|`s
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TableRow {
+    pub span: SnoteSpan,
+    /// a `|---+---|` line, its cells are the dashes
+    pub separator: bool,
+    pub cells: Vec<SnoteSpan>,
+}
+
+/// Something that can be marked as done, either a `[ ]` checkbox on a list
+/// item or a `TODO`/`DONE` keyword on a headline
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Task {
+    pub done: bool,
+    pub kind: TaskKind,
+    pub span: SnoteSpan,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TaskKind {
+    Checkbox,
+    Keyword,
+}
+
+/// The `-`, `+` or `1.` starting a list item
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ListMarker {
+    pub kind: ListKind,
+    pub span: SnoteSpan,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ListKind {
+    Bullet(char),
+    Ordered(usize),
+}
+
+impl ListKind {
+    /// The marker of the item after one marked with this
+    pub fn next(&self) -> String {
+	match self {
+	    ListKind::Bullet(bullet) => bullet.to_string(),
+	    ListKind::Ordered(number) => format!("{}.", number + 1),
+	}
+    }
+}
+
+impl Task {
+    /// The text that should replace `span` to flip the task's state,
+    /// it always has the same length as the original
+    pub fn toggled(&self) -> &'static str {
+	match (self.kind, self.done) {
+	    (TaskKind::Checkbox, true) => "[ ]",
+	    (TaskKind::Checkbox, false) => "[x]",
+	    (TaskKind::Keyword, true) => "TODO",
+	    (TaskKind::Keyword, false) => "DONE",
+	}
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Markup {
+    Bold,
+    Italic,
+    Code,
+    Strike,
+    Underline,
 }
 
 
@@ -58,16 +593,193 @@ use SNoteSection::*;
 impl SNoteSection {
     pub fn span(&self) -> Range<usize> {
 	match self{
-            Paragraph(sp) |
-            Headline(sp) => sp.clone()
+            Paragraph(sp, _) |
+            Headline{span: sp, ..} |
+	    ListItem{span: sp, ..} |
+	    Markup(_, sp) |
+	    Tag(sp) |
+	    Link(sp) |
+	    Url(sp) |
+	    CodeBlock{span: sp, ..} |
+	    Table{span: sp, ..} |
+	    Properties{span: sp, ..} => sp.clone()
+	}
+    }
+    fn span_mut(&mut self) -> &mut SnoteSpan {
+	match self{
+            Paragraph(sp, _) |
+            Headline{span: sp, ..} |
+	    ListItem{span: sp, ..} |
+	    Markup(_, sp) |
+	    Tag(sp) |
+	    Link(sp) |
+	    Url(sp) |
+	    CodeBlock{span: sp, ..} |
+	    Table{span: sp, ..} |
+	    Properties{span: sp, ..} => sp
+	}
+    }
+    /// Moves the section and everything inside it by `delta` bytes
+    pub(crate) fn shift(&mut self, delta: isize) {
+	let moved = |at: usize| (at as isize + delta) as usize;
+	let span = self.span_mut();
+	*span = moved(span.start)..moved(span.end);
+	match self{
+	    Headline{task, inlines, children, ..} => {
+		if let Some(task) = task {
+		    task.span = moved(task.span.start)..moved(task.span.end);
+		}
+		inlines.iter_mut().chain(children).for_each(|child| child.shift(delta));
+	    },
+	    ListItem{marker, task, inlines, children, ..} => {
+		marker.span = moved(marker.span.start)..moved(marker.span.end);
+		if let Some(task) = task {
+		    task.span = moved(task.span.start)..moved(task.span.end);
+		}
+		inlines.iter_mut().chain(children).for_each(|child| child.shift(delta));
+	    },
+	    Paragraph(_, children) => children.iter_mut().for_each(|child| child.shift(delta)),
+	    CodeBlock{language, code, ..} => {
+		if let Some(language) = language {
+		    *language = moved(language.start)..moved(language.end);
+		}
+		*code = moved(code.start)..moved(code.end);
+	    },
+	    Table{rows, ..} => rows.iter_mut().for_each(|row| {
+		row.span = moved(row.span.start)..moved(row.span.end);
+		row.cells.iter_mut().for_each(|cell| *cell = moved(cell.start)..moved(cell.end));
+	    }),
+	    Properties{properties, ..} => properties.iter_mut().for_each(|property| {
+		for span in [&mut property.span, &mut property.key, &mut property.value] {
+		    *span = moved(span.start)..moved(span.end);
+		}
+	    }),
+	    Markup(..) | Tag(_) | Link(_) | Url(_) => {},
 	}
     }
     pub fn content_span(&self) -> Range<usize> {
 	match self{
-            Paragraph(sp) |
-            Headline(sp) => sp.clone()
+            Paragraph(sp, _) |
+	    ListItem{span: sp, ..} => sp.clone(),
+            Headline{level, span, ..} => (span.start + level + 1)..span.end,
+	    Markup(_, sp) => (sp.start + 1)..(sp.end - 1),
+	    Tag(sp) => (sp.start + 1)..sp.end,
+	    Link(sp) => (sp.start + 2)..(sp.end - 2),
+	    Url(sp) => sp.clone(),
+	    CodeBlock{code, ..} => code.clone(),
+	    Table{span, ..} |
+	    Properties{span, ..} => span.clone(),
+	}
+    }
+    /// The headline depth, everything else is at level 0
+    pub fn level(&self) -> usize {
+	match self{
+	    Headline{level, ..} => *level,
+	    _ => 0,
+	}
+    }
+    /// How far a list item's marker is indented, everything else is at 0
+    pub fn indent(&self) -> usize {
+	match self{
+	    ListItem{marker, span, ..} => marker.span.start - span.start,
+	    _ => 0,
+	}
+    }
+    /// Sections nested inside this one, paragraphs own their inline markup,
+    /// headlines own everything below them and list items their sub items
+    pub fn children(&self) -> &[SNoteSection] {
+	match self{
+	    Paragraph(_, children) |
+	    Headline{children, ..} |
+	    ListItem{children, ..} => children,
+	    Markup(..) | Tag(_) | Link(_) | Url(_) | CodeBlock{..} | Table{..} | Properties{..} => &[],
 	}
     }
+    /// The markup, tags and links on the section's own lines
+    pub fn inlines(&self) -> &[SNoteSection] {
+	match self{
+	    Paragraph(_, inlines) |
+	    Headline{inlines, ..} |
+	    ListItem{inlines, ..} => inlines,
+	    Markup(..) | Tag(_) | Link(_) | Url(_) | CodeBlock{..} | Table{..} | Properties{..} => &[],
+	}
+    }
+    pub fn task(&self) -> Option<&Task> {
+	match self{
+	    Headline{task, ..} |
+	    ListItem{task, ..} => task.as_ref(),
+	    _ => None,
+	}
+    }
+    /// The task on the line of the section containing `pos`, a position
+    /// right at the end of a section (the end of the note) also counts
+    pub fn task_at(sections: &[SNoteSection], pos: usize) -> Option<&Task> {
+	Self::find_task(sections, &|sp| sp.contains(&pos))
+	    .or_else(|| Self::find_task(sections, &|sp| sp.end == pos))
+    }
+
+    /// The tags in `sections` and everything inside them
+    pub fn tags(sections: &[SNoteSection]) -> Vec<&SNoteSection> {
+	Self::find_inlines(sections, &|section| matches!(section, Tag(_)))
+    }
+
+    /// The links in `sections` and everything inside them
+    pub fn links(sections: &[SNoteSection]) -> Vec<&SNoteSection> {
+	Self::find_inlines(sections, &|section| matches!(section, Link(_)))
+    }
+
+    fn find_inlines<'s>(sections: &'s [SNoteSection], wanted: &dyn Fn(&SNoteSection) -> bool)
+			-> Vec<&'s SNoteSection> {
+	sections.iter()
+	    .flat_map(|section| match (wanted(section), section) {
+		(true, _) => vec![section],
+		(false, Headline{inlines, children, ..} | ListItem{inlines, children, ..}) =>
+		    Self::find_inlines(inlines, wanted)
+		    .into_iter()
+		    .chain(Self::find_inlines(children, wanted))
+		    .collect(),
+		(false, _) => Self::find_inlines(section.inlines(), wanted),
+	    })
+	    .collect()
+    }
+
+    /// The markup, tag, link or url at byte `pos`
+    pub fn inline_at(sections: &[SNoteSection], pos: usize) -> Option<&SNoteSection> {
+	sections.iter().find_map(|section| {
+	    let here = section.span().contains(&pos);
+	    match section {
+		Markup(..) | Tag(_) | Link(_) | Url(_) => here.then_some(section),
+		// a headline's span is only its own line
+		_ => here.then(|| Self::inline_at(section.inlines(), pos))
+		    .flatten()
+		    .or_else(|| Self::inline_at(section.children(), pos)),
+	    }
+	})
+    }
+
+    /// The list item on the line at `pos`, as the sections of its list and
+    /// its index among them
+    pub(crate) fn list_item_at(sections: &[SNoteSection], pos: usize) -> Option<(&[SNoteSection], usize)> {
+	Self::find_list_item(sections, &|sp| sp.contains(&pos))
+	    .or_else(|| Self::find_list_item(sections, &|sp| sp.end == pos))
+    }
+
+    fn find_list_item<'s>(sections: &'s [SNoteSection], at: &dyn Fn(Range<usize>) -> bool)
+			  -> Option<(&'s [SNoteSection], usize)> {
+	sections.iter().enumerate().find_map(|(i, s)| match (at(s.span()), s) {
+	    (true, ListItem{..}) => Some((sections, i)),
+	    (true, _) => None,
+	    (false, _) => Self::find_list_item(s.children(), at),
+	})
+    }
+
+    fn find_task<'s>(sections: &'s [SNoteSection], at: &dyn Fn(Range<usize>) -> bool)
+		     -> Option<&'s Task> {
+	sections.iter().find_map(|s| match at(s.span()) {
+	    true => s.task(),
+	    false => Self::find_task(s.children(), at),
+	})
+    }
 }
 
 #[cfg(test)]
@@ -76,7 +788,11 @@ mod tests {
 
     use chumsky::Parser;
 
-    use super::{SNoteSection, block, blocks, headline, newlines_or_end, snote};
+    use super::{ListKind, ListMarker, Markup, SNoteSection, Task, TaskKind, block, blocks, headline, newlines_or_end, parse_blocks, snote};
+
+    fn marker(kind: ListKind, span: Range<usize>) -> ListMarker {
+	ListMarker{kind, span}
+    }
 
     #[test]
     fn empty_snote_is_valid(){
@@ -89,7 +805,7 @@ mod tests {
         let (ast, _err) = block().parse_recovery_verbose(paragraph);
         eprintln!("{:?}", ast);
         assert!(ast.is_some());
-        assert_eq!(SNoteSection::Paragraph(0..paragraph.len()), ast.unwrap());
+        assert_eq!(SNoteSection::Paragraph(0..paragraph.len(), vec![]), ast.unwrap());
     }
     #[test]
     fn parse_a_file_ending_block() {
@@ -98,7 +814,7 @@ mod tests {
         eprintln!("{:?}", _err);
         eprintln!("{:?}", ast);
         assert!(ast.is_some());
-        assert_eq!(SNoteSection::Paragraph(0..paragraph.len()), ast.unwrap());
+        assert_eq!(SNoteSection::Paragraph(0..paragraph.len(), vec![]), ast.unwrap());
     }
 
     #[test]
@@ -107,7 +823,27 @@ mod tests {
         let (ast, _err) = headline().parse_recovery_verbose(format!("* {}", the_headline));
         eprintln!("{:?}", ast);
         assert!(ast.is_some());
-        assert_eq!(SNoteSection::Headline(0..the_headline.len()+2), ast.unwrap());
+        assert_eq!(
+	    SNoteSection::Headline{level: 1, task: None, span: 0..the_headline.len()+2, inlines: vec![], children: vec![]},
+	    ast.unwrap()
+	);
+    }
+
+    #[test]
+    fn parse_nested_headline() {
+        let (ast, _err) = block().parse_recovery_verbose("*** deep\n\n");
+        assert_eq!(
+	    Some(SNoteSection::Headline{level: 3, task: None, span: 0..10, inlines: vec![], children: vec![]}),
+	    ast
+	);
+    }
+
+    #[test]
+    fn stars_without_space_are_not_a_headline() {
+        let (ast, _err) = snote().parse_recovery_verbose("*bold* text");
+        assert_eq!(Some(vec![SNoteSection::Paragraph(0..11, vec![
+	    SNoteSection::Markup(Markup::Bold, 0..6)
+	])]), ast);
     }
 
     #[test]
@@ -124,10 +860,290 @@ mod tests {
 	if !_err.is_empty() {dbg!(_err);}
         assert!(ast.is_some());
         assert_eq!(
-            expected.into_iter().map(SNoteSection::Paragraph).collect::<Vec<_>>(),
+            expected.into_iter().map(|sp| SNoteSection::Paragraph(sp, vec![])).collect::<Vec<_>>(),
             ast.unwrap()
         );
     }
+
+    #[test]
+    fn parse_inline_markup() {
+        let line = "a *bold* /italic/ `co de` ~strike~ _under_ end";
+        let (ast, _err) = block().parse_recovery_verbose(line);
+        assert_eq!(
+	    Some(SNoteSection::Paragraph(0..line.len(), vec![
+		SNoteSection::Markup(Markup::Bold, 2..8),
+		SNoteSection::Markup(Markup::Italic, 9..17),
+		SNoteSection::Markup(Markup::Code, 18..25),
+		SNoteSection::Markup(Markup::Strike, 26..34),
+		SNoteSection::Markup(Markup::Underline, 35..42),
+	    ])),
+	    ast
+	);
+    }
+
+    #[test]
+    fn markup_characters_inside_words_are_plain_text() {
+        for line in ["snake_case_name", "a/b/c", "2*3*4", "* not bold*", "*not bold *", "*unclosed"] {
+            let (ast, _err) = snote().parse_recovery_verbose(line);
+            let ast = ast.unwrap();
+            assert!(ast.iter().all(|s| s.level() > 0 || s.children().is_empty()), "{}: {:?}", line, ast);
+        }
+    }
+
+    #[test]
+    fn markup_doesnt_span_lines() {
+        let (ast, _err) = block().parse_recovery_verbose("*not\nbold*");
+        assert_eq!(Some(SNoteSection::Paragraph(0..10, vec![])), ast);
+    }
+
+    #[test]
+    fn parse_checkbox_list_items() {
+        let note = "- [ ] open\n- [x] *done*\n\nplain line";
+        let (ast, _err) = snote().parse_recovery_verbose(note);
+        assert_eq!(
+	    Some(vec![
+		SNoteSection::ListItem{
+		    marker: marker(ListKind::Bullet('-'), 0..1),
+		    task: Some(Task{done: false, kind: TaskKind::Checkbox, span: 2..5}),
+		    span: 0..11,
+		    inlines: vec![],
+		    children: vec![],
+		},
+		SNoteSection::ListItem{
+		    marker: marker(ListKind::Bullet('-'), 11..12),
+		    task: Some(Task{done: true, kind: TaskKind::Checkbox, span: 13..16}),
+		    span: 11..25,
+		    inlines: vec![SNoteSection::Markup(Markup::Bold, 17..23)],
+		    children: vec![],
+		},
+		SNoteSection::Paragraph(25..35, vec![]),
+	    ]),
+	    ast
+	);
+    }
+
+    #[test]
+    fn nested_lists() {
+        let note = "1. one\n   - a\n     + deep\n   - b\n2. two\n-not an item\n";
+        let (ast, errs) = snote().parse_recovery(note);
+        assert!(errs.is_empty());
+        fn outline(note: &str, sections: &[SNoteSection]) -> Vec<String> {
+	    sections.iter()
+		.flat_map(|section| {
+		    let line = format!("{}{}", " ".repeat(section.indent()), note[section.span()].trim());
+		    std::iter::once(line).chain(outline(note, section.children()).into_iter().map(|l| format!(">{}", l)))
+		})
+		.collect()
+        }
+        let ast = ast.unwrap();
+        assert_eq!(
+	    vec!["1. one", ">   - a", ">>     + deep", ">   - b", "2. two", "-not an item"],
+	    outline(note, &ast)
+        );
+        match &ast[1] {
+	    SNoteSection::ListItem{marker, ..} => assert_eq!(ListKind::Ordered(2), marker.kind),
+	    other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_tables() {
+        let note = "| a | bb |\n|---+:-:|\n  | ccc |  \n| half\n|-x|\n";
+        let (flat, errs) = parse_blocks(note, 0);
+        assert!(errs.is_empty());
+        let rows = match &flat[0] {
+	    SNoteSection::Table{span, rows} => {
+		assert_eq!(0..note.len(), *span);
+		rows.iter()
+		    .map(|row| (row.separator, row.cells.iter().map(|cell| &note[cell.clone()]).collect::<Vec<_>>()))
+		    .collect::<Vec<_>>()
+	    },
+	    other => panic!("{:?}", other),
+        };
+        assert_eq!(vec![
+	    (false, vec![" a ", " bb "]),
+	    (true, vec!["---", ":-:"]),
+	    (false, vec![" ccc "]),
+	    (false, vec![" half"]),
+	    (false, vec!["-x"]),
+        ], rows);
+    }
+
+    #[test]
+    fn parse_property_drawers() {
+        let note = "* title\n:PROPERTIES:\n:created: 2022-01-01\n:Source:\n:END:\ntext\n:properties:\n:a: b";
+        let (flat, errs) = parse_blocks(note, 0);
+        assert!(errs.is_empty());
+        let drawers: Vec<_> = flat.iter()
+	    .filter_map(|block| match block {
+		SNoteSection::Properties{properties, ..} => Some(properties.iter()
+		    .map(|p| (&note[p.key.clone()], &note[p.value.clone()]))
+		    .collect::<Vec<_>>()),
+		_ => None,
+	    })
+	    .collect();
+        assert_eq!(vec![vec![("created", " 2022-01-01"), ("Source", "")], vec![("a", " b")]], drawers);
+        assert_eq!("text\n", &note[flat[2].span()]);
+    }
+
+    #[test]
+    fn parse_todo_keywords() {
+        let (ast, _err) = headline().parse_recovery_verbose("** TODO call mom");
+        assert_eq!(
+	    Some(&Task{done: false, kind: TaskKind::Keyword, span: 3..7}),
+	    ast.as_ref().and_then(SNoteSection::task)
+	);
+        let (ast, _err) = headline().parse_recovery_verbose("* DONE");
+        assert_eq!(Some(true), ast.as_ref().and_then(SNoteSection::task).map(|t| t.done));
+        let (ast, _err) = headline().parse_recovery_verbose("* TODOS aren't keywords");
+        assert_eq!(None, ast.as_ref().and_then(SNoteSection::task));
+    }
+
+    #[test]
+    fn parse_tags() {
+        let note = "* TODO plan :work:q3:\nsee #Budget-2 and C#, #1 or a#b\n* not :tags: here";
+        let (ast, _err) = snote().parse_recovery_verbose(note);
+        let ast = ast.unwrap();
+        let tags: Vec<_> = SNoteSection::tags(&ast).into_iter()
+	    .map(|tag| &note[tag.content_span()])
+	    .collect();
+        assert_eq!(vec!["work", "q3", "Budget-2"], tags);
+        assert_eq!(
+	    Some(&SNoteSection::Tag(12..17)),
+	    SNoteSection::tags(&ast).first().copied()
+	);
+    }
+
+    #[test]
+    fn parse_wiki_links() {
+        let note = "* see [[Project X]]\n- [[other note]] and [[]] or [[a\nb]]";
+        let (ast, errs) = snote().parse_recovery(note);
+        assert!(errs.is_empty());
+        let ast = ast.unwrap();
+        let links: Vec<_> = SNoteSection::links(&ast).into_iter()
+	    .map(|link| &note[link.content_span()])
+	    .collect();
+        assert_eq!(vec!["Project X", "other note"], links);
+        assert_eq!(Some(&SNoteSection::Link(22..36)), SNoteSection::inline_at(&ast, 25));
+        assert_eq!(None, SNoteSection::inline_at(&ast, 2));
+    }
+
+    #[test]
+    fn parse_urls() {
+        let note = "* docs https://example.com/a_(b)?q=1.\nsee (http://x.org), file:~/a.pdf and xhttp://no";
+        let (ast, errs) = snote().parse_recovery(note);
+        assert!(errs.is_empty());
+        let mut urls = vec![];
+        let mut pos = 0;
+        while pos < note.len() {
+	    match SNoteSection::inline_at(ast.as_ref().unwrap(), pos) {
+		Some(url @ SNoteSection::Url(span)) => {
+		    urls.push(&note[url.content_span()]);
+		    pos = span.end;
+		},
+		_ => pos += 1,
+	    }
+        }
+        assert_eq!(vec!["https://example.com/a_(b)?q=1", "http://x.org", "file:~/a.pdf"], urls);
+    }
+
+    #[test]
+    fn parse_code_blocks() {
+        let note = "intro\n```sh\necho *a*\n\n* b\n```\n\n#+begin_src python\nx = 1\n#+END_SRC\n```inline``` code\n```\nopen\n\nto the end";
+        let (ast, errs) = snote().parse_recovery(note);
+        assert!(errs.is_empty());
+        let blocks: Vec<_> = ast.unwrap().into_iter()
+	    .map(|block| match block {
+		SNoteSection::CodeBlock{language, code, ..} =>
+		    format!("{}: {:?}", language.map_or("", |l| &note[l]), &note[code]),
+		other => format!("{:?}", &note[other.span()]),
+	    })
+	    .collect();
+        assert_eq!(vec![
+	    "\"intro\\n\"",
+	    "sh: \"echo *a*\\n\\n* b\\n\"",
+	    "python: \"x = 1\\n\"",
+	    "\"```inline``` code\\n\"",
+	    ": \"open\\n\\nto the end\"",
+	], blocks);
+    }
+
+    #[test]
+    fn find_the_task_under_the_cursor() {
+        let note = "* TODO a\n- [ ] b\n- [x] c";
+        let (ast, _err) = snote().parse_recovery_verbose(note);
+        let ast = ast.unwrap();
+	let task_kind_at = |pos| SNoteSection::task_at(&ast, pos).map(|t| (t.kind, t.done));
+        assert_eq!(Some((TaskKind::Keyword, false)), task_kind_at(0));
+        assert_eq!(Some((TaskKind::Checkbox, false)), task_kind_at(9));
+        assert_eq!(Some((TaskKind::Checkbox, true)), task_kind_at(note.len()));
+        let toggled = SNoteSection::task_at(&ast, 9).unwrap().toggled();
+        assert_eq!("[x]", toggled);
+    }
+
+    #[test]
+    fn inlines_right_after_punctuation() {
+	let note = "foo,[[bar]] see:https://x.org a,#tag but C#, foo#bar and x/y_z*";
+	let (ast, errs) = snote().parse_recovery(note);
+	assert!(errs.is_empty(), "{:?}", errs);
+	let inlines = match ast.as_deref() {
+	    Some([SNoteSection::Paragraph(_, inlines)]) => inlines.iter().map(|inline| &note[inline.span()]).collect::<Vec<_>>(),
+	    other => panic!("{:?}", other),
+	};
+	assert_eq!(vec!["[[bar]]", "https://x.org", "#tag"], inlines);
+    }
+
+    #[test]
+    fn brackets_that_arent_checkboxes_start_the_text() {
+	for note in ["- [draft] idea", "- [docs](http://x.org) here", "1. [a] option"] {
+	    let (ast, errs) = snote().parse_recovery(note);
+	    assert!(errs.is_empty(), "{:?}", errs);
+	    match ast.as_deref() {
+		Some([SNoteSection::ListItem{task: None, span, ..}]) => assert_eq!(0..note.len(), *span),
+		other => panic!("{:?} parsed as {:?}", note, other),
+	    }
+	}
+    }
+
+    #[test]
+    fn malformed_lines_are_recovered_as_paragraphs() {
+        let note = "* headline\n- [?] typo\n\n- [ ] fine";
+        let (ast, errs) = snote().parse_recovery(note);
+        assert_eq!(1, errs.len());
+        assert_eq!(14..15, errs[0].span());
+        assert_eq!(
+	    Some(vec![SNoteSection::Headline{
+		level: 1,
+		task: None,
+		span: 0..11,
+		inlines: vec![],
+		children: vec![
+		    SNoteSection::Paragraph(11..23, vec![]),
+		    SNoteSection::ListItem{
+			marker: marker(ListKind::Bullet('-'), 23..24),
+			task: Some(Task{done: false, kind: TaskKind::Checkbox, span: 25..28}),
+			span: 23..33,
+			inlines: vec![],
+			children: vec![],
+		    },
+		],
+	    }]),
+	    ast
+	);
+    }
+
+    #[test]
+    fn parsed_spans_are_byte_offsets() {
+        let note = "* כותרת\nשלום *עולם*";
+        let (flat, errs) = parse_blocks(note, 0);
+        assert!(errs.is_empty());
+        assert_eq!("* כותרת\n", &note[flat[0].span()]);
+        assert_eq!("*עולם*", &note[flat[1].children()[0].span()]);
+
+        let (flat, _errs) = parse_blocks("- [x] a", 10);
+        assert_eq!(Some(12..15), flat[0].task().map(|t| t.span.clone()));
+    }
+
     fn move_range(r: &Range<usize>, offset: usize) -> Range<usize>{
 	(r.start+offset)..(r.end+offset)
     }
@@ -165,22 +1181,66 @@ mod tests {
         let the_paragraph = "the\nfirst\nparagraph";
         let the_second_paragraph = "the second\n paragraph";
 	let (s, expected_snot) = make_snote(the_headline, [the_paragraph, the_second_paragraph]);
-	dbg!(&s);
+        let (flat, _err) = blocks().parse_recovery_verbose(s.as_str());
+        assert!(flat.is_some());
+	flat.as_ref().unwrap().windows(2)
+	    .for_each(|sections| assert_eq!(sections[0].span().end, sections[1].span().start));
         let (ast, _err) = snote().parse_recovery_verbose(s);
-        eprintln!("{:?}", ast);
-        assert!(ast.is_some());
-	ast.as_ref().unwrap().windows(2)
-	    .map(|sections|(sections[0].clone(), sections[1].clone()))
-	    .for_each(|(s1, s2)|{
-	    dbg!(&s1, &s2);
-	    assert_eq!(s1.span().end, s2.span().start);
-	});
         assert_eq!(
             expected_snot,
             ast.unwrap()
         );
     }
 
+    #[test]
+    fn paragraphs_before_the_first_headline() {
+        let note = "intro\n\n* headline\nbody";
+        let (ast, _err) = snote().parse_recovery_verbose(note);
+        assert_eq!(
+	    Some(vec![
+		SNoteSection::Paragraph(0..7, vec![]),
+		SNoteSection::Headline{
+		    level: 1,
+		    task: None,
+		    span: 7..18,
+		    inlines: vec![],
+		    children: vec![SNoteSection::Paragraph(18..22, vec![])]
+		},
+	    ]),
+	    ast
+	);
+    }
+
+    #[test]
+    fn headlines_own_deeper_headlines() {
+        let note = "* a\n** b\nb body\n*** c\n** d\n* e\n";
+        let (ast, _err) = snote().parse_recovery_verbose(note);
+        let ast = ast.unwrap();
+	let levels = |sections: &[SNoteSection]|
+	    sections.iter().map(SNoteSection::level).collect::<Vec<_>>();
+        assert_eq!(vec![1, 1], levels(&ast));
+        assert_eq!(vec![2, 2], levels(ast[0].children()));
+        assert_eq!(vec![0, 3], levels(ast[0].children()[0].children()));
+        assert_eq!(
+	    SNoteSection::Headline{level: 3, task: None, span: 16..22, inlines: vec![], children: vec![]},
+	    ast[0].children()[0].children()[1]
+	);
+        assert!(ast[1].children().is_empty());
+    }
+
+    #[test]
+    fn a_headline_ends_the_paragraph_above_it() {
+        let note = "first line\n** headline";
+        let (ast, _err) = snote().parse_recovery_verbose(note);
+        assert_eq!(
+	    Some(vec![
+		SNoteSection::Paragraph(0..11, vec![]),
+		SNoteSection::Headline{level: 2, task: None, span: 11..22, inlines: vec![], children: vec![]},
+	    ]),
+	    ast
+	);
+    }
+
     fn make_paragraphs(paragraphs: impl IntoIterator<Item=impl AsRef<str>>)
 		       -> (String, Vec<Range<usize>>){
 	paragraphs.into_iter().scan(0, |l, p|{
@@ -194,14 +1254,19 @@ mod tests {
     fn make_snote(headline: impl AsRef<str>, paragraphs: impl IntoIterator<Item=impl AsRef<str>>)
 		  -> (String, Vec<SNoteSection>){
 	let headline_text = format!("* {}\n", headline.as_ref());
-	let headline_span = SNoteSection::Headline(0..headline_text.len());
 	let (par_text, par_spans) = make_paragraphs(paragraphs);
-	let spans = std::iter::once(headline_span).into_iter()
-	    .chain(par_spans.into_iter()
-		   .map(|sp|move_range(&sp, headline_text.len()))
-		   .map(SNoteSection::Paragraph))
+	let children = par_spans.into_iter()
+	    .map(|sp|move_range(&sp, headline_text.len()))
+	    .map(|sp| SNoteSection::Paragraph(sp, vec![]))
 	    .collect();
-	(headline_text + &par_text, spans)
+	let headline_span = SNoteSection::Headline{
+	    level: 1,
+	    task: None,
+	    span: 0..headline_text.len(),
+	    inlines: vec![],
+	    children
+	};
+	(headline_text + &par_text, vec![headline_span])
     }
 
     #[test]
@@ -3,8 +3,18 @@
 #![cfg_attr(debug_assertions, allow(dead_code))]
 
 use eframe::egui;
+use snote2::settings::build_config;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export") {
+	if let Err(e) = snote2::export::run(&args[1..]) {
+	    eprintln!("{}", e);
+	    std::process::exit(1);
+	}
+	return;
+    }
+
     let options = eframe::NativeOptions {
         decorated: false,
         transparent: true,
@@ -14,13 +24,24 @@ fn main() {
     };
 
     // tracing_subscriber::fmt::init();
+    let settings = match build_config() {
+	Ok(settings) => settings,
+	Err(e) => {
+	    eprintln!("can't read the settings: {}", e);
+	    std::process::exit(1);
+	},
+    };
 
     eframe::run_native(
         "eframe template",
         options,
-        Box::new(|cc| {
+        Box::new(move |cc| {
             cc.egui_ctx.set_visuals(eframe::egui::Visuals::dark());
-            Box::new(snote2::app::Snotter::default())
+	    let time_format = settings.get_string("timestamp_format").unwrap();
+	    let persist_undo = settings.get_bool("persist_undo").unwrap();
+            Box::new(snote2::app::Snotter::default()
+		     .with_time_format(time_format)
+		     .with_persisted_undo(persist_undo))
         }),
     );
 }
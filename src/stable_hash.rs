@@ -0,0 +1,19 @@
+/// A 64 bit FNV-1a hash of `bytes`, unlike `DefaultHasher` it's the same
+/// with every Rust release, so it can be kept in files
+pub(crate) fn stable_hash(bytes: impl AsRef<[u8]>) -> u64 {
+    bytes.as_ref().iter().fold(0xcbf29ce484222325, |hash, byte| {
+	(hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stable_hash;
+
+    #[test]
+    fn hashes_dont_change() {
+	assert_eq!(0xcbf29ce484222325, stable_hash(""));
+	assert_eq!(0xaf63dc4c8601ec8c, stable_hash("a"));
+	assert_eq!(0x85944171f73967e8, stable_hash("foobar"));
+    }
+}
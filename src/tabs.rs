@@ -0,0 +1,235 @@
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+
+use crate::saving::SaveState;
+use crate::snote::SNote;
+
+/// A note open in the editor
+#[derive(Debug)]
+pub(crate) struct Tab {
+    pub path: PathBuf,
+    pub note: SNote,
+    pub saved: SaveState,
+    /// keeps the editor's cursor and scroll position apart from other tabs'
+    pub id: egui::Id,
+    /// whether what the file had when the tab was opened has a snapshot
+    pub original_kept: bool,
+}
+
+/// The open notes, one of them shown in the editor at a time
+#[derive(Debug, Default)]
+pub(crate) struct Tabs {
+    tabs: Vec<Tab>,
+    active: usize,
+    /// how many tabs were ever opened, so every tab gets a new id
+    opened: u64,
+}
+
+impl Tab {
+    pub fn name(&self) -> String {
+	self.path.file_name()
+	    .map(|name| name.to_string_lossy().to_string())
+	    .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    /// The name, marked when the tab has unsaved edits or a conflict
+    fn title(&self) -> String {
+	match (&self.saved.conflict, self.saved.is_dirty()) {
+	    (Some(_), _) => format!("{} !", self.name()),
+	    (None, true) => format!("{} •", self.name()),
+	    (None, false) => self.name(),
+	}
+    }
+}
+
+impl Tabs {
+    /// Shows the tab of `path`, `load`ing the note into a new tab if it
+    /// isn't open yet
+    pub fn open(&mut self, path: PathBuf, load: impl FnOnce(&Path) -> (SNote, SaveState)) {
+	self.active = match self.position(&path) {
+	    Some(open) => open,
+	    None => {
+		self.opened += 1;
+		let id = egui::Id::new(("snote_tab", self.opened));
+		let (note, saved) = load(&path);
+		self.tabs.push(Tab{note, saved, path, id, original_kept: false});
+		self.tabs.len() - 1
+	    },
+	};
+    }
+
+    /// Shows a closed tab again
+    pub fn reopen(&mut self, tab: Tab) {
+	self.tabs.push(tab);
+	self.active = self.tabs.len() - 1;
+    }
+
+    pub fn active(&self) -> Option<&Tab> {
+	self.tabs.get(self.active)
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut Tab> {
+	self.tabs.get_mut(self.active)
+    }
+
+    pub fn find_mut(&mut self, path: &Path) -> Option<&mut Tab> {
+	self.tabs.iter_mut().find(|tab| tab.path == path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tab> {
+	self.tabs.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Tab> {
+	self.tabs.iter_mut()
+    }
+
+    fn position(&self, path: &Path) -> Option<usize> {
+	self.tabs.iter().position(|tab| tab.path == path)
+    }
+
+    /// Closes the tab at `index`, the tab after it is shown if it was active.
+    /// Tabs with a conflict stay open until it's resolved.
+    pub fn close(&mut self, index: usize) -> Option<Tab> {
+	if self.tabs.get(index).is_none_or(|tab| tab.saved.conflict.is_some()) {
+	    return None;
+	}
+	let closed = self.tabs.remove(index);
+	if index < self.active || self.active == self.tabs.len() {
+	    self.active = self.active.saturating_sub(1);
+	}
+	Some(closed)
+    }
+
+    pub fn close_active(&mut self) -> Option<Tab> {
+	self.close(self.active)
+    }
+
+    pub fn next(&mut self) {
+	if !self.tabs.is_empty() {
+	    self.active = (self.active + 1) % self.tabs.len();
+	}
+    }
+
+    pub fn prev(&mut self) {
+	if !self.tabs.is_empty() {
+	    self.active = (self.active + self.tabs.len() - 1) % self.tabs.len();
+	}
+    }
+
+    /// Ctrl+Tab and Ctrl+Shift+Tab cycle through the tabs, Ctrl+W closes one,
+    /// returns the closed tab
+    pub fn handle_shortcuts(&mut self, ui: &mut egui::Ui) -> Option<Tab> {
+	use egui::{Key, Modifiers};
+	let mut input = ui.input_mut();
+	if input.consume_key(Modifiers::CTRL | Modifiers::SHIFT, Key::Tab) {
+	    self.prev();
+	} else if input.consume_key(Modifiers::CTRL, Key::Tab) {
+	    self.next();
+	} else if input.consume_key(Modifiers::CTRL, Key::W) {
+	    return self.close_active();
+	}
+	None
+    }
+
+    /// A row with a button for every tab, middle clicking one closes it,
+    /// returns the closed tab
+    pub fn tab_bar(&mut self, ui: &mut egui::Ui) -> Option<Tab> {
+	let (mut shown, mut closed) = (None, None);
+	ui.horizontal_wrapped(|ui| {
+	    for (i, tab) in self.tabs.iter().enumerate() {
+		let label = ui.selectable_label(i == self.active, tab.title());
+		if label.clicked() {
+		    shown = Some(i);
+		}
+		if label.middle_clicked() || ui.small_button("×").clicked() {
+		    closed = Some(i);
+		}
+	    }
+	});
+	if let Some(shown) = shown {
+	    self.active = shown;
+	}
+	closed.and_then(|closed| self.close(closed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::saving::{Conflict, SaveState};
+    use crate::snote::SNote;
+
+    use super::Tabs;
+
+    fn unsaved(_: &std::path::Path) -> (SNote, SaveState) {
+	(SNote::new(), SaveState::default())
+    }
+
+    fn tabs_of(names: &[&str]) -> Tabs {
+	let mut tabs = Tabs::default();
+	names.iter().for_each(|name| tabs.open(name.into(), unsaved));
+	tabs
+    }
+
+    fn active(tabs: &Tabs) -> Option<PathBuf> {
+	tabs.active().map(|tab| tab.path.clone())
+    }
+
+    #[test]
+    fn opening_an_open_note_shows_its_tab() {
+	let mut tabs = tabs_of(&["a", "b"]);
+	assert_eq!(Some("b".into()), active(&tabs));
+	tabs.open("a".into(), |_| panic!("a is already loaded"));
+	assert_eq!(Some("a".into()), active(&tabs));
+	assert_eq!(2, tabs.iter().count());
+	let ids: Vec<_> = tabs.iter().map(|tab| tab.id).collect();
+	assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn cycling_wraps_around() {
+	let mut tabs = tabs_of(&["a", "b", "c"]);
+	tabs.next();
+	assert_eq!(Some("a".into()), active(&tabs));
+	tabs.prev();
+	tabs.prev();
+	assert_eq!(Some("b".into()), active(&tabs));
+    }
+
+    #[test]
+    fn closing_shows_a_neighbouring_tab() {
+	let mut tabs = tabs_of(&["a", "b", "c"]);
+	tabs.open("b".into(), unsaved);
+	tabs.close_active();
+	assert_eq!(Some("c".into()), active(&tabs));
+	tabs.close_active();
+	assert_eq!(Some("a".into()), active(&tabs));
+	tabs.close(5);
+	tabs.close_active();
+	assert_eq!(None, active(&tabs));
+	tabs.next();
+	assert!(tabs.close_active().is_none());
+    }
+
+    #[test]
+    fn conflicted_tabs_stay_open() {
+	let mut tabs = tabs_of(&["a"]);
+	tabs.active_mut().unwrap().saved.conflict = Some(Conflict::new("theirs"));
+	assert!(tabs.close_active().is_none());
+	let theirs = tabs.active_mut().unwrap().saved.reload();
+	assert_eq!(Some("theirs".to_string()), theirs);
+	assert!(tabs.close_active().is_some());
+	tabs.reopen(tabs_of(&["b"]).close_active().unwrap());
+	assert_eq!(Some("b".into()), active(&tabs));
+    }
+
+    #[test]
+    fn closing_a_tab_before_the_active_one_keeps_it_shown() {
+	let mut tabs = tabs_of(&["a", "b", "c"]);
+	tabs.close(0);
+	assert_eq!(Some("c".into()), active(&tabs));
+    }
+}
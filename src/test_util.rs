@@ -0,0 +1,35 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory for a test's files, removed when it's dropped so
+/// failing tests don't leave it behind either
+pub(crate) struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    pub fn new(name: &str) -> Self {
+	let dir = std::env::temp_dir().join(format!("snott-{}-{}", name, std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	Self(dir)
+    }
+}
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+	&self.0
+    }
+}
+
+impl AsRef<Path> for ScratchDir {
+    fn as_ref(&self) -> &Path {
+	&self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+	let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, channel};
+
+use eframe::egui;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::folders::is_hidden;
+
+/// Watches a notes directory and everything under it
+#[derive(Debug)]
+pub(crate) struct NoteWatcher {
+    dir: PathBuf,
+    events: Receiver<notify::Result<Event>>,
+    // events stop once the watcher is dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl NoteWatcher {
+    /// Starts watching `dir`, `ctx` is repainted when something changes so
+    /// the changes are picked up even while the app is idle
+    pub fn watch(dir: &Path, ctx: egui::Context) -> notify::Result<Self> {
+	let (sender, events) = channel();
+	let mut watcher = notify::recommended_watcher(move |event| {
+	    if sender.send(event).is_ok() {
+		ctx.request_repaint();
+	    }
+	})?;
+	watcher.watch(dir, RecursiveMode::Recursive)?;
+	Ok(Self{dir: dir.to_path_buf(), events, _watcher: watcher})
+    }
+
+    /// The paths changed since the last call, reading files doesn't count
+    pub fn changed_paths(&self) -> notify::Result<BTreeSet<PathBuf>> {
+	let mut changed = BTreeSet::new();
+	for event in self.events.try_iter() {
+	    let event = event?;
+	    if matches!(event.kind, EventKind::Access(_)) {
+		continue;
+	    }
+	    changed.extend(event.paths.into_iter().filter(|path| !self.is_hidden(path)));
+	}
+	Ok(changed)
+    }
+
+    /// Whether `path` is in a hidden directory or is a hidden file, like the
+    /// temporary files notes are saved through
+    fn is_hidden(&self, path: &Path) -> bool {
+	path.strip_prefix(&self.dir)
+	    .map(|relative| relative.ancestors().any(is_hidden))
+	    .unwrap_or(true)
+    }
+}